@@ -0,0 +1,108 @@
+//! Compares the radix tree router against the previous per-method map,
+//! which looked up static paths in a `HashMap` and scanned the patterns.
+//!
+//! Run with `cargo bench --bench router`.
+
+use rust_server::core::server::{RadixTree, RoutePattern};
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RESOURCES: usize = 100;
+const ITERATIONS: usize = 200;
+
+/// The previous router, static paths in a map and patterns sorted by precedence.
+struct MapRouter {
+    exact: HashMap<String, usize>,
+    patterns: Vec<(RoutePattern, usize)>,
+}
+
+impl MapRouter {
+    fn new(routes: &[String]) -> Self {
+        let mut router = MapRouter {
+            exact: HashMap::new(),
+            patterns: Vec::new(),
+        };
+        for (i, route) in routes.iter().enumerate() {
+            let pattern = RoutePattern::parse(route).unwrap();
+            if pattern.is_static() {
+                router.exact.insert(route.clone(), i);
+            } else {
+                router.patterns.push((pattern, i));
+            }
+        }
+        router.patterns.sort_by(|(a, _), (b, _)| a.precedence(b));
+        router
+    }
+
+    fn lookup(&self, path: &str) -> Option<usize> {
+        if let Some(value) = self.exact.get(path) {
+            return Some(*value);
+        }
+        self.patterns
+            .iter()
+            .find_map(|(pattern, value)| pattern.matches(path).map(|_| *value))
+    }
+}
+
+/// A few hundred routes in the shape of a typical REST api.
+fn routes() -> Vec<String> {
+    let mut routes = vec!["/".to_string(), "/*".to_string()];
+    for i in 0..RESOURCES {
+        routes.push(format!("/api/v1/resource{}", i));
+        routes.push(format!("/api/v1/resource{}/:id", i));
+        routes.push(format!("/api/v1/resource{}/:id/items/*rest", i));
+    }
+    routes
+}
+
+fn paths() -> Vec<String> {
+    let mut paths = vec![];
+    for i in (0..RESOURCES).step_by(7) {
+        paths.push(format!("/api/v1/resource{}", i));
+        paths.push(format!("/api/v1/resource{}/42", i));
+        paths.push(format!("/api/v1/resource{}/42/items/a/b/c", i));
+    }
+    paths.push("/assets/index.css".to_string());
+    paths
+}
+
+fn measure(name: &str, paths: &[String], lookup: impl Fn(&str) -> Option<usize>) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        for path in paths {
+            black_box(lookup(black_box(path)));
+        }
+    }
+    let elapsed = start.elapsed();
+    let per_lookup = elapsed / (ITERATIONS * paths.len()) as u32;
+    println!("{:<12} {:>10?} per lookup", name, per_lookup);
+    per_lookup
+}
+
+fn main() {
+    let routes = routes();
+    let paths = paths();
+
+    let map = MapRouter::new(&routes);
+    let mut tree = RadixTree::new();
+    for (i, route) in routes.iter().enumerate() {
+        tree.insert(route, i).unwrap();
+    }
+
+    // both routers must agree before comparing them
+    for path in &paths {
+        let expected = map.lookup(path);
+        assert_eq!(tree.lookup(path).map(|(v, _)| *v), expected, "{}", path);
+    }
+
+    println!("{} routes, {} paths", routes.len(), paths.len());
+    let map_time = measure("map", &paths, |path| map.lookup(path));
+    let tree_time = measure("radix tree", &paths, |path| {
+        tree.lookup(path).map(|(v, _)| *v)
+    });
+    println!(
+        "radix tree is {:.1}x faster",
+        map_time.as_secs_f64() / tree_time.as_secs_f64()
+    );
+}
@@ -0,0 +1,333 @@
+use rust_server::core::data::log::LogLevel;
+use rust_server::core::http::{self, HttpResponse};
+use rust_server::core::server::{Server, ServerConfig, StaticFiles};
+use rust_server::core::Request;
+use std::path::PathBuf;
+
+const USAGE: &str = "\
+Serve a directory of static files over HTTP.
+
+Usage: rust-server [serve] [DIR] [OPTIONS]
+
+Arguments:
+  [DIR]  Directory to serve, defaults to the configured public_dir
+
+Options:
+  -p, --port <PORT>      Port to listen on [default: 8080]
+      --host <HOST>      Address to listen on [default: localhost]
+      --spa              Serve index.html for unknown paths without an extension
+      --cors             Allow cross-origin requests from any origin
+      --autoindex        List the contents of directories without an index.html
+  -w, --workers <N>      Number of worker threads
+  -c, --config <FILE>    Config file [default: rust-server.toml if present]
+  -q, --quiet            Only log errors
+  -h, --help             Print this help
+  -V, --version          Print the version
+
+Settings are read from the config file, then RUST_SERVER_* environment
+variables, then these options.
+
+Exit status: 0 after a clean shutdown, 1 if the server failed, 2 for usage errors.";
+
+/// Exit status after a clean shutdown.
+pub const EXIT_OK: i32 = 0;
+/// Exit status when the server can't start, e.g. the port is taken.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit status for invalid arguments.
+pub const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub dir: Option<PathBuf>,
+    pub host: Option<String>,
+    pub port: Option<String>,
+    pub workers: Option<String>,
+    pub config: Option<PathBuf>,
+    pub spa: bool,
+    pub cors: bool,
+    pub autoindex: bool,
+    pub quiet: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Serve(Options),
+    Help,
+    Version,
+}
+
+/// Parses the arguments after the program name. Options take their value
+/// either as the next argument or after `=`, e.g. `--port=3000`.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter().peekable();
+
+    if args.peek().map(String::as_str) == Some("serve") {
+        args.next();
+    }
+
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| match inline {
+            Some(value) => Ok(value.to_string()),
+            None => args
+                .next()
+                .filter(|value| !value.starts_with('-'))
+                .ok_or_else(|| format!("{} requires a value", name)),
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-p" | "--port" => options.port = Some(value("--port")?),
+            "--host" => options.host = Some(value("--host")?),
+            "-w" | "--workers" => options.workers = Some(value("--workers")?),
+            "-c" | "--config" => options.config = Some(PathBuf::from(value("--config")?)),
+            "--spa" => options.spa = true,
+            "--cors" => options.cors = true,
+            "--autoindex" => options.autoindex = true,
+            "-q" | "--quiet" => options.quiet = true,
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            _ if options.dir.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => options.dir = Some(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(Command::Serve(options))
+}
+
+/// Runs the command line, returning the exit status.
+pub fn run(args: impl IntoIterator<Item = String>) -> i32 {
+    let options = match parse_args(args) {
+        Ok(Command::Serve(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return EXIT_OK;
+        }
+        Ok(Command::Version) => {
+            println!("rust-server {}", env!("CARGO_PKG_VERSION"));
+            return EXIT_OK;
+        }
+        Err(e) => {
+            eprintln!(
+                "rust-server: {}\n\nTry 'rust-server --help' for more information.",
+                e
+            );
+            return EXIT_USAGE;
+        }
+    };
+
+    // invalid settings are usage errors, whether from the options, the
+    // config file or the environment
+    let config = match config(&options, std::env::vars()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!(
+                "rust-server: {}\n\nTry 'rust-server --help' for more information.",
+                e
+            );
+            return EXIT_USAGE;
+        }
+    };
+    let mut server = match Server::from_config(config) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("rust-server: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+
+    if let Ok(addrs) = server.local_addrs() {
+        for addr in addrs {
+            println!(
+                "Serving {} at http://{}",
+                server.config().public_dir.display(),
+                addr
+            );
+        }
+    }
+
+    let config = server.config();
+    let mut files = StaticFiles::new(&config.public_dir);
+    if let Some(page) = &config.not_found_page {
+        // configured relative to the working directory rather than the root
+        files = files.not_found_page(std::path::absolute(page).unwrap_or(page.clone()));
+    }
+    if options.spa {
+        files = files.fallback("index.html");
+    }
+    if options.cors {
+        files = files.header("Access-Control-Allow-Origin", "*");
+    }
+    if options.autoindex {
+        files = files.autoindex(true);
+    }
+
+    server.configure(|route| {
+        route.mount("/", files);
+        if options.cors {
+            route.def("OPTIONS", "*", preflight);
+        }
+    });
+
+    server.start();
+    EXIT_OK
+}
+
+/// Builds the config from the file and environment variables, then the options.
+fn config(
+    options: &Options,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> std::io::Result<ServerConfig> {
+    let mut config = ServerConfig::load_with(options.config.as_deref(), vars)?;
+    config.handle_signals = true;
+
+    if let Some(dir) = &options.dir {
+        config.set("static.public_dir", &dir.to_string_lossy())?;
+    }
+    if let Some(host) = &options.host {
+        config.set("server.host", host)?;
+    }
+    if let Some(port) = &options.port {
+        config.set("server.port", port)?;
+    }
+    if let Some(workers) = &options.workers {
+        config.set("server.workers", workers)?;
+    }
+    if options.quiet {
+        config.log_level = LogLevel::Error;
+    }
+
+    config.validate()?;
+    Ok(config)
+}
+
+/// Answers CORS preflight requests for any path.
+fn preflight(request: &mut Request) -> http::Response {
+    request.send(
+        HttpResponse::no_content()
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Access-Control-Allow-Methods", "GET, HEAD, OPTIONS")
+            .header("Access-Control-Allow-Headers", "*")
+            .header("Access-Control-Max-Age", "86400"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn args(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn serve(list: &[&str]) -> Options {
+        match args(list) {
+            Ok(Command::Serve(options)) => options,
+            other => panic!("{:?} parsed as {:?}", list, other),
+        }
+    }
+
+    #[test]
+    fn parses_options_and_the_directory() {
+        let options = serve(&[
+            "serve",
+            "./dist",
+            "-p",
+            "3000",
+            "--host=0.0.0.0",
+            "--spa",
+            "--cors",
+            "-w",
+            "2",
+            "-q",
+        ]);
+        assert_eq!(
+            options,
+            Options {
+                dir: Some(PathBuf::from("./dist")),
+                host: Some("0.0.0.0".to_string()),
+                port: Some("3000".to_string()),
+                workers: Some("2".to_string()),
+                spa: true,
+                cors: true,
+                quiet: true,
+                ..Options::default()
+            }
+        );
+        assert_eq!(serve(&[]), Options::default());
+    }
+
+    #[test]
+    fn parses_help_and_version() {
+        assert_eq!(args(&["--help"]), Ok(Command::Help));
+        assert_eq!(args(&["serve", "-p", "80", "-h"]), Ok(Command::Help));
+        assert_eq!(args(&["-V"]), Ok(Command::Version));
+    }
+
+    #[test]
+    fn refuses_unknown_flags_and_missing_values() {
+        assert_eq!(
+            args(&["--prot", "80"]),
+            Err("unknown option --prot".to_string())
+        );
+        assert_eq!(args(&["-x"]), Err("unknown option -x".to_string()));
+        assert_eq!(
+            args(&["--port"]),
+            Err("--port requires a value".to_string())
+        );
+        assert_eq!(
+            args(&["--port", "--spa"]),
+            Err("--port requires a value".to_string())
+        );
+        assert_eq!(
+            args(&["./a", "./b"]),
+            Err("unexpected argument ./b".to_string())
+        );
+    }
+
+    #[test]
+    fn options_override_the_environment_which_overrides_the_file() {
+        let dir = std::env::temp_dir().join(format!("rust-server-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config.toml");
+        fs::write(
+            &file,
+            "[server]\nhost = \"0.0.0.0\"\nport = 1111\nworkers = 3\nmax_queued = 7\n",
+        )
+        .unwrap();
+        let vars = || {
+            vec![
+                ("RUST_SERVER_PORT".to_string(), "2222".to_string()),
+                ("RUST_SERVER_WORKERS".to_string(), "5".to_string()),
+            ]
+        };
+
+        let mut options = serve(&["-c", file.to_str().unwrap(), "-p", "3333"]);
+        let built = config(&options, vars()).unwrap();
+        assert_eq!(built.addrs, vec!["0.0.0.0:3333"]);
+        assert_eq!(built.threads, 5);
+        assert_eq!(built.max_queued, 7);
+
+        options.port = None;
+        assert_eq!(
+            config(&options, vars()).unwrap().addrs,
+            vec!["0.0.0.0:2222"]
+        );
+
+        options.port = Some("http".to_string());
+        assert!(config(&options, vars()).is_err());
+        options.port = None;
+        options.workers = Some("0".to_string());
+        assert!(config(&options, vars()).is_err());
+        options.workers = None;
+        options.dir = Some(dir.join("missing"));
+        assert!(config(&options, vars()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,487 @@
+//! DEFLATE compression (RFC 1951) with the gzip (RFC 1952) and zlib
+//! (RFC 1950) wrappers used by the `gzip` and `deflate` content codings.
+//!
+//! Matches are found with hash chains over a 32 KiB window and encoded with
+//! the fixed Huffman codes, which keeps the encoder small while still
+//! shrinking text by well over half. Data which doesn't shrink is stored.
+
+static WINDOW_SIZE: usize = 32768;
+static MIN_MATCH: usize = 3;
+static MAX_MATCH: usize = 258;
+static HASH_BITS: u32 = 15;
+/// How many earlier positions are tried for each match.
+static MAX_CHAIN: usize = 64;
+/// Largest payload of a stored block.
+static MAX_STORED: usize = 65535;
+
+static LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+static LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+static DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+static DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Compresses data into a raw DEFLATE stream.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let compressed = compress_fixed(data);
+    // a stored stream costs 5 bytes per block on top of the data
+    let stored_size = data.len() + 5 * data.len().div_ceil(MAX_STORED).max(1);
+    match compressed.len() < stored_size {
+        true => compressed,
+        false => store(data),
+    }
+}
+
+/// Compresses data into the gzip format, the `gzip` content coding.
+///
+/// ```
+/// use rust_server::core::data::deflate::gzip;
+///
+/// let compressed = gzip(&b"hello hello hello hello hello".repeat(20));
+/// assert_eq!(compressed[..2], [0x1f, 0x8b]);
+/// assert!(compressed.len() < 100);
+/// ```
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    // magic, deflate, no flags, no modification time, no extra flags, unknown OS
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend(deflate(data));
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+/// Compresses data into the zlib format, the `deflate` content coding.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32 KiB window, no dictionary, header check bits
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate(data));
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+/// The CRC-32 checksum used by gzip.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+/// The Adler-32 checksum used by zlib.
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // the sums can't overflow within a chunk of this size
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Writes bits least significant first, as DEFLATE packs them.
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new(capacity: usize) -> Self {
+        BitWriter {
+            out: Vec::with_capacity(capacity),
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn write(&mut self, value: u32, length: u32) {
+        self.bits |= u64::from(value) << self.count;
+        self.count += length;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which is packed most significant bit first.
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+/// Writes a literal/length symbol with the fixed Huffman code.
+fn write_literal(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASE
+        .iter()
+        .rposition(|&base| base as usize <= length)
+        .unwrap_or(0);
+    write_literal(writer, 257 + code as u32);
+    writer.write(
+        (length - LENGTH_BASE[code] as usize) as u32,
+        u32::from(LENGTH_EXTRA[code]),
+    );
+
+    let code = DISTANCE_BASE
+        .iter()
+        .rposition(|&base| base as usize <= distance)
+        .unwrap_or(0);
+    writer.write_code(code as u32, 5);
+    writer.write(
+        (distance - DISTANCE_BASE[code] as usize) as u32,
+        u32::from(DISTANCE_EXTRA[code]),
+    );
+}
+
+fn hash(data: &[u8], position: usize) -> usize {
+    let bytes = u32::from(data[position])
+        | u32::from(data[position + 1]) << 8
+        | u32::from(data[position + 2]) << 16;
+    (bytes.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Compresses into a single block using the fixed Huffman codes.
+fn compress_fixed(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new(data.len() / 2 + 16);
+    // final block, fixed Huffman codes
+    writer.write(1, 1);
+    writer.write(1, 2);
+
+    // the most recent position for each hash and the one before it for each position
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW_SIZE];
+
+    let mut position = 0;
+    while position < data.len() {
+        let (length, distance) = longest_match(data, position, &head, &previous);
+        if length >= MIN_MATCH {
+            write_match(&mut writer, length, distance);
+            for skipped in position..position + length {
+                insert(data, skipped, &mut head, &mut previous);
+            }
+            position += length;
+        } else {
+            write_literal(&mut writer, u32::from(data[position]));
+            insert(data, position, &mut head, &mut previous);
+            position += 1;
+        }
+    }
+
+    write_literal(&mut writer, 256);
+    writer.finish()
+}
+
+/// Adds a position to the hash chains.
+fn insert(data: &[u8], position: usize, head: &mut [usize], previous: &mut [usize]) {
+    if position + MIN_MATCH <= data.len() {
+        let hash = hash(data, position);
+        previous[position % WINDOW_SIZE] = head[hash];
+        head[hash] = position;
+    }
+}
+
+/// Finds the longest earlier match for the bytes at `position` within the
+/// window, returning its length and distance.
+fn longest_match(
+    data: &[u8],
+    position: usize,
+    head: &[usize],
+    previous: &[usize],
+) -> (usize, usize) {
+    if position + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+
+    let max_length = MAX_MATCH.min(data.len() - position);
+    let (mut best_length, mut best_distance) = (0, 0);
+    let mut candidate = head[hash(data, position)];
+
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || position - candidate > WINDOW_SIZE - 1 {
+            break;
+        }
+        let length = data[candidate..]
+            .iter()
+            .zip(&data[position..position + max_length])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if length > best_length {
+            (best_length, best_distance) = (length, position - candidate);
+            if length == max_length {
+                break;
+            }
+        }
+
+        let next = previous[candidate % WINDOW_SIZE];
+        // older positions in the slot have been overwritten by newer ones
+        if next == usize::MAX || next >= candidate {
+            break;
+        }
+        candidate = next;
+    }
+
+    (best_length, best_distance)
+}
+
+/// Wraps data in stored blocks without compressing it.
+fn store(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 5 * (data.len() / MAX_STORED + 1));
+    let mut blocks = data.chunks(MAX_STORED).peekable();
+    if blocks.peek().is_none() {
+        return vec![1, 0, 0, 0xff, 0xff];
+    }
+
+    while let Some(block) = blocks.next() {
+        // final flag, stored block type, padded to a byte boundary
+        out.push(u8::from(blocks.peek().is_none()));
+        out.extend((block.len() as u16).to_le_bytes());
+        out.extend((!(block.len() as u16)).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads bits least significant first.
+    struct BitReader<'a> {
+        data: &'a [u8],
+        position: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bits(&mut self, count: u32) -> u32 {
+            let mut value = 0;
+            for i in 0..count {
+                let byte = self.data[self.position / 8];
+                value |= u32::from((byte >> (self.position % 8)) & 1) << i;
+                self.position += 1;
+            }
+            value
+        }
+
+        /// Reads a Huffman code of `count` bits, most significant first.
+        fn code(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |code, _| code << 1 | self.bits(1))
+        }
+
+        fn literal(&mut self) -> u32 {
+            let code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code;
+            }
+            let code = code << 1 | self.bits(1);
+            match code {
+                0x30..=0xbf => code - 0x30,
+                0xc0..=0xc7 => 280 + code - 0xc0,
+                _ => 144 + (code << 1 | self.bits(1)) - 0x190,
+            }
+        }
+    }
+
+    /// A minimal inflate for the stored and fixed Huffman blocks the
+    /// encoder writes, panicking on anything else.
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut reader = BitReader { data, position: 0 };
+        let mut out = Vec::new();
+        loop {
+            let last = reader.bits(1) == 1;
+            match reader.bits(2) {
+                0 => {
+                    let start = reader.position.div_ceil(8);
+                    let length = u16::from_le_bytes([data[start], data[start + 1]]) as usize;
+                    let check = u16::from_le_bytes([data[start + 2], data[start + 3]]);
+                    assert_eq!(check, !(length as u16));
+                    out.extend_from_slice(&data[start + 4..start + 4 + length]);
+                    reader.position = (start + 4 + length) * 8;
+                }
+                1 => loop {
+                    let symbol = reader.literal();
+                    match symbol {
+                        0..=255 => out.push(symbol as u8),
+                        256 => break,
+                        _ => {
+                            let code = symbol as usize - 257;
+                            let length = LENGTH_BASE[code] as usize
+                                + reader.bits(u32::from(LENGTH_EXTRA[code])) as usize;
+                            let code = reader.code(5) as usize;
+                            let distance = DISTANCE_BASE[code] as usize
+                                + reader.bits(u32::from(DISTANCE_EXTRA[code])) as usize;
+                            assert!(distance <= out.len() && distance <= WINDOW_SIZE);
+                            for _ in 0..length {
+                                out.push(out[out.len() - distance]);
+                            }
+                        }
+                    }
+                },
+                kind => panic!("unexpected block type {}", kind),
+            }
+            if last {
+                assert_eq!(reader.position.div_ceil(8), data.len(), "trailing data");
+                return out;
+            }
+        }
+    }
+
+    /// Deterministic pseudo-random bytes.
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// Text built from a small vocabulary, so it repeats but not regularly.
+    fn text(length: usize) -> Vec<u8> {
+        let words = [
+            "static ", "files ", "are ", "served ", "gzip ", "quickly ", "\n",
+        ];
+        noise(length)
+            .iter()
+            .flat_map(|byte| words[*byte as usize % words.len()].bytes())
+            .take(length)
+            .collect()
+    }
+
+    fn inputs() -> Vec<Vec<u8>> {
+        vec![
+            vec![],
+            b"a".to_vec(),
+            b"hello, world".to_vec(),
+            b"ab".repeat(5000),
+            vec![0; 100_000],
+            text(200_000),
+            noise(70_000),
+            (0..=255).collect(),
+        ]
+    }
+
+    #[test]
+    fn computes_known_checksums() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414f_a339
+        );
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        // long enough for the sums to be reduced between chunks
+        assert_eq!(adler32(&[0xff; 6000]), 0xa497_59ea);
+    }
+
+    #[test]
+    fn inflate_reads_zlib_output() {
+        // zlib's raw deflate with fixed Huffman codes, so the round trips
+        // below can't pass on a bug shared by the encoder and `inflate`
+        let compressed = [
+            0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x3a, 0x0a, 0xc5, 0x25, 0x89,
+            0x25, 0x99, 0xc9, 0x0a, 0x69, 0x99, 0x39, 0xa9, 0xc5, 0x0a, 0x89, 0x45, 0xa9, 0x0a,
+            0xc5, 0xa9, 0x45, 0x65, 0xa9, 0x29, 0x10, 0x69, 0x45, 0x00,
+        ];
+        assert_eq!(
+            inflate(&compressed),
+            b"hello hello hello, static files are served hello!"
+        );
+    }
+
+    #[test]
+    fn deflate_round_trips() {
+        for input in inputs() {
+            let compressed = deflate(&input);
+            assert_eq!(inflate(&compressed), input, "{} bytes", input.len());
+        }
+    }
+
+    #[test]
+    fn compresses_repetitive_data() {
+        assert!(deflate(&b"ab".repeat(5000)).len() < 100);
+        assert!(deflate(&vec![0; 100_000]).len() < 1000);
+
+        // matches reach back across the whole window
+        let text = text(200_000);
+        assert!(deflate(&text).len() < text.len() / 2);
+    }
+
+    #[test]
+    fn stores_incompressible_data() {
+        // a fixed block holding only the end of block code
+        assert_eq!(deflate(b""), [3, 0]);
+        assert_eq!(store(b""), [1, 0, 0, 0xff, 0xff]);
+        assert_eq!(inflate(&store(b"")), b"");
+
+        let noise = noise(70_000);
+        let compressed = deflate(&noise);
+        // two stored blocks, as one holds at most 65535 bytes
+        assert_eq!(compressed.len(), noise.len() + 10);
+        assert_eq!(compressed[0], 0);
+        assert_eq!(compressed[5 + MAX_STORED], 1);
+    }
+
+    #[test]
+    fn wraps_gzip() {
+        for input in inputs() {
+            let compressed = gzip(&input);
+            assert_eq!(compressed[..4], [0x1f, 0x8b, 8, 0]);
+            let (body, trailer) = compressed[10..].split_at(compressed.len() - 18);
+            assert_eq!(inflate(body), input);
+            assert_eq!(trailer[..4], crc32(&input).to_le_bytes());
+            assert_eq!(trailer[4..], (input.len() as u32).to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn wraps_zlib() {
+        for input in inputs() {
+            let compressed = zlib(&input);
+            // the header's check bits make it a multiple of 31
+            assert_eq!(u16::from_be_bytes([compressed[0], compressed[1]]) % 31, 0);
+            let (body, trailer) = compressed[2..].split_at(compressed.len() - 6);
+            assert_eq!(inflate(body), input);
+            assert_eq!(trailer, adler32(&input).to_be_bytes());
+        }
+    }
+}
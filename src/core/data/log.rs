@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much the server prints, `Info` traces every connection and request
+/// to stdout while `Error` only reports failures to stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off,
+    Error,
+    #[default]
+    Info,
+}
+
+impl LogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Info => "info",
+        }
+    }
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level.to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(LogLevel::Off),
+            "error" => Ok(LogLevel::Error),
+            "info" => Ok(LogLevel::Info),
+            _ => Err(format!("unknown log level: {}", level)),
+        }
+    }
+}
+
+/// The level is process wide since log lines come from every module.
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Off,
+        1 => LogLevel::Error,
+        _ => LogLevel::Info,
+    }
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level <= self::level() && level != LogLevel::Off
+}
+
+/// Prints to stdout when the log level is `Info`.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::core::data::log::enabled($crate::core::data::log::LogLevel::Info) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints to stderr unless logging is off.
+macro_rules! error {
+    ($($arg:tt)*) => {
+        if $crate::core::data::log::enabled($crate::core::data::log::LogLevel::Error) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use error;
+pub(crate) use info;
@@ -39,3 +39,22 @@ pub fn get_mime_type(path: &str) -> String {
     };
     mime_type.to_string()
 }
+
+/// Whether a type usually shrinks when compressed, unlike images, video
+/// and archives which are compressed already. Parameters are ignored.
+pub fn is_compressible(mime: &str) -> bool {
+    let mime = mime.split(';').next().unwrap_or_default().trim();
+    let mime = mime.to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/json"
+                | "application/xml"
+                | "application/javascript"
+                | "application/wasm"
+                | "image/svg+xml"
+                | "image/x-icon"
+        )
+}
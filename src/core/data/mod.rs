@@ -1,2 +1,4 @@
+pub mod deflate;
+pub mod log;
 pub mod mime;
 pub mod util;
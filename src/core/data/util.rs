@@ -1,90 +1,510 @@
+use crate::core::data::log::error;
 use crate::core::get_mime_type;
 use crate::core::http;
+use crate::core::http::date::{format_http_date, parse_http_date};
+use crate::core::http::range::content_range;
+use crate::core::http::{ByteRanges, ContentEncoding, HttpResponse};
+use crate::core::middleware::compression::variant_tag;
 use crate::core::Request;
+use std::collections::HashMap;
 use std::f32::consts::PI;
 use std::fmt::format;
 use std::fs::{self, File};
-use std::io::{self, Write};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-static HTTP_CRLF: &[u8] = b"\r\n";
-static HTTP_VERSION: &[u8] = b"HTTP/1.1";
-static HTML_NOT_FOUND: &str = "src/public/404.html";
-static PUBLIC_DIR: &str = "src/public";
-static INVALID_CHARS: [&str; 4] = ["..", "~", "\\", " "];
+/// The configured public directory, kept in the server's `State`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublicDir(pub PathBuf);
 
-pub fn sanitize_path(path: &str) -> String {
-    let trimmed = path.trim();
-    INVALID_CHARS
-        .iter()
-        .fold(trimmed.to_string(), |acc, &c| acc.replace(c, ""))
-        .replace("//", "/")
-        .to_string()
+/// The configured page sent when a static file doesn't exist, kept in the
+/// server's `State`. Defaults to `404.html` in the public directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotFoundPage(pub PathBuf);
+
+/// The directory static files are served from unless configured otherwise.
+pub static PUBLIC_DIR: &str = "src/public";
+/// The most compressed file data `send_file_encoded` keeps in memory.
+static ENCODED_CACHE_SIZE: usize = 32 * 1024 * 1024;
+/// Decodes `%XX` escapes in a uri component, invalid escapes are kept as is
+/// and invalid UTF-8 is replaced.
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
 }
 
-pub fn public(path: &str) -> String {
-    if path.starts_with(PUBLIC_DIR) {
-        path.to_string()
-    } else if path.starts_with("/") {
-        format!("{}{}", PUBLIC_DIR, path)
-    } else {
-        format!("{}/{}", PUBLIC_DIR, path)
+/// Encodes everything but unreserved characters as `%XX`, for putting a
+/// file name in a uri path.
+pub fn percent_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
     }
+    encoded
 }
 
 pub fn find_static_file(uri: &str) -> String {
-    let path = sanitize_path(uri);
-    let file_path = match path.as_str() {
-        "" | "/" => format!("{}/index.html", PUBLIC_DIR),
-        path if path.ends_with("/") => format!("{}index.html", path),
-        _ => path.to_string(),
-    };
-    let public_path = public(&file_path);
-    let file_exists = match fs::exists(&public_path) {
-        Ok(exists) => exists,
+    let dir = Path::new(PUBLIC_DIR);
+    find_static_file_in(dir, &dir.join("404.html"), uri)
+}
+
+/// Finds the file for a uri under `dir`, falling back to the `not_found` page.
+/// Directories are served by their `index.html`.
+pub fn find_static_file_in(dir: &Path, not_found: &Path, uri: &str) -> String {
+    match static_file_in(dir, uri) {
+        Some(file) => file.display().to_string(),
+        None => not_found.display().to_string(),
+    }
+}
+
+/// Finds the file for a uri under `dir` with `resolve_path`, serving
+/// directories by their `index.html`. Returns `None` if there is none.
+pub fn static_file_in(dir: &Path, uri: &str) -> Option<PathBuf> {
+    resolve_path(dir, uri).and_then(|path| match path.is_dir() {
+        true => resolve_path(&path, "index.html"),
+        false => Some(path),
+    })
+}
+
+/// Resolves a request path to an existing file or directory under `root`.
+///
+/// The path is percent-decoded and split into segments, any `..` segment is
+/// refused rather than stripped, and the result is canonicalized and checked
+/// to still be under the canonical `root`, so neither encoded traversal
+/// (`%2e%2e`) nor a symlink can escape it. Returns `None` if the path is
+/// refused or doesn't exist.
+pub fn resolve_path(root: &Path, uri: &str) -> Option<PathBuf> {
+    let path = uri.split(['?', '#']).next().unwrap_or_default();
+    let decoded = percent_decode(path);
+
+    let mut relative = PathBuf::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            segment if segment.contains(['\\', '\0']) => return None,
+            segment => relative.push(segment),
+        }
+    }
+
+    let root = root.canonicalize().ok()?;
+    let resolved = match root.join(relative).canonicalize() {
+        Ok(resolved) => resolved,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
         Err(e) => {
-            eprintln!("[util] error checking file: {}", e);
-            false
+            error!("[util] error resolving {}: {}", path, e);
+            return None;
         }
     };
+    resolved.starts_with(&root).then_some(resolved)
+}
 
-    if file_exists {
-        // println!("[util] found file: {}", public_path);
-        public_path
-    } else {
-        HTML_NOT_FOUND.to_string()
+pub fn copy_static_file(request: &mut Request, path: String) -> http::Response {
+    send_file(request, &path, HttpResponse::ok())
+}
+
+/// Streams a file as the body of `head`, which sets the status and any extra
+/// headers, adding its `Content-Length` and its `Content-Type` unless `head`
+/// sets one. Only the head is sent in reply to a `HEAD` request.
+///
+/// When `head` is `200 OK` the file is sent with an `ETag` and
+/// `Last-Modified` derived from its size and modification time, and a
+/// request whose `If-None-Match` or `If-Modified-Since` matches them gets
+/// `304 Not Modified` instead.
+///
+/// The `Range` header of a `GET` request is honoured too, see `ByteRanges`.
+/// A single range is sent with `206 Partial Content`, several as
+/// `multipart/byteranges`, and ranges outside the file get `416 Range Not
+/// Satisfiable`. With `If-Range` the whole file is sent unless the entity
+/// tag or date matches.
+pub fn send_file(request: &mut Request, path: &str, head: HttpResponse) -> http::Response {
+    send_file_encoded(request, path, head, ContentEncoding::Identity)
+}
+
+/// Sends a file like `send_file`, compressed with `encoding` if the server
+/// can encode it. The file is compressed in memory, the compressed body gets
+/// its own entity tag, and ranges apply to the compressed bytes.
+///
+/// Compressed files are cached by path and entity tag, so a file is only
+/// compressed again once it changes, keeping up to 32 MiB of them in all.
+pub fn send_file_encoded(
+    request: &mut Request,
+    path: &str,
+    head: HttpResponse,
+    encoding: ContentEncoding,
+) -> http::Response {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let encoding = match encoding.can_encode() {
+        true => encoding,
+        false => ContentEncoding::Identity,
+    };
+
+    let mut head = head;
+    if head.status_code() == 200 {
+        let etag = match encoding {
+            ContentEncoding::Identity => entity_tag(&metadata),
+            encoding => variant_tag(&entity_tag(&metadata), encoding),
+        };
+        head = head.header("Accept-Ranges", "bytes").header("ETag", etag);
+        if let Ok(modified) = metadata.modified() {
+            head = head.header("Last-Modified", format_http_date(modified));
+        }
+        if not_modified(request, &head) {
+            return request.send(head.status(304));
+        }
     }
+
+    // a type set by the caller wins, e.g. for a precompressed `.gz` file
+    let content_type = match head.headers().get("Content-Type") {
+        Some(content_type) => content_type.to_string(),
+        None => get_mime_type(path),
+    };
+    head.headers_mut().remove("Content-Type");
+
+    if encoding == ContentEncoding::Identity {
+        return send_body(request, file, metadata.len(), &content_type, head);
+    }
+
+    let etag = variant_tag(&entity_tag(&metadata), encoding);
+    let encoded = encode_file(&mut file, path, etag, encoding)?;
+    let size = encoded.len() as u64;
+    let head = head.header("Content-Encoding", encoding.as_str());
+    send_body(request, Cursor::new(encoded), size, &content_type, head)
 }
 
-pub fn copy_static_file(request: &mut Request, path: String) -> http::Response {
-    let mut reader = File::open(&path)?;
-    let mut bytes_sent = 0;
-    let mut writer = request.stream();
+/// Compressed files by path and the entity tag of the encoded variant.
+#[derive(Default)]
+struct EncodedCache {
+    files: HashMap<(String, String), Arc<[u8]>>,
+    size: usize,
+}
 
-    // write status code
-    bytes_sent += writer.write(b"HTTP/2.0 200 OK")?;
-    bytes_sent += writer.write(HTTP_CRLF)?;
+fn encoded_cache() -> &'static Mutex<EncodedCache> {
+    static CACHE: OnceLock<Mutex<EncodedCache>> = OnceLock::new();
+    CACHE.get_or_init(Mutex::default)
+}
 
-    let mime = get_mime_type(&path);
-    let size = reader.metadata()?.len();
+/// Reads and compresses a file, or returns the copy compressed before.
+fn encode_file(
+    file: &mut File,
+    path: &str,
+    etag: String,
+    encoding: ContentEncoding,
+) -> io::Result<Arc<[u8]>> {
+    let key = (path.to_string(), etag);
+    let cached = encoded_cache()
+        .lock()
+        .ok()
+        .and_then(|cache| cache.files.get(&key).cloned());
+    if let Some(encoded) = cached {
+        return Ok(encoded);
+    }
 
-    bytes_sent += writer.write(format!("Content-Type: {}", mime).as_bytes())?;
-    bytes_sent += writer.write(HTTP_CRLF)?;
-    bytes_sent += writer.write(format!("Content-Length: {}", size).as_bytes())?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let encoded: Arc<[u8]> = encoding.encode(&data).unwrap_or(data).into();
+
+    if let Ok(mut cache) = encoded_cache().lock() {
+        // entries of files which changed are never read again, so rather
+        // than tracking them the cache starts over once it's full
+        if cache.size + encoded.len() > ENCODED_CACHE_SIZE {
+            cache.files.clear();
+            cache.size = 0;
+        }
+        if encoded.len() <= ENCODED_CACHE_SIZE {
+            cache.size += encoded.len();
+            if let Some(previous) = cache.files.insert(key, encoded.clone()) {
+                cache.size -= previous.len();
+            }
+        }
+    }
+    Ok(encoded)
+}
 
-    // end headers
-    bytes_sent += writer.write(HTTP_CRLF)?;
-    bytes_sent += writer.write(HTTP_CRLF)?;
+/// Sends `size` bytes from `reader`, or the ranges of them asked for.
+fn send_body(
+    request: &mut Request,
+    mut reader: impl Read + Seek,
+    size: u64,
+    mime: &str,
+    head: HttpResponse,
+) -> http::Response {
+    let ranges = match request.headers().get("Range") {
+        Some(range) if head.status_code() == 200 && request.method == "GET" => {
+            match if_range_matches(request, &head) {
+                true => ByteRanges::parse(range, size),
+                false => ByteRanges::Full,
+            }
+        }
+        _ => ByteRanges::Full,
+    };
 
-    if bytes_sent == 0 {
-        return Err(io::Error::new(
-            io::ErrorKind::WriteZero,
-            "Failed to write response",
-        ));
+    let ranges = match ranges {
+        ByteRanges::Full => {
+            let head = head
+                .header("Content-Type", mime)
+                .header("Content-Length", size);
+            let status = request.send_head(head)?;
+            if request.method != "HEAD" {
+                io::copy(&mut reader, &mut request.stream())?;
+            }
+            return Ok(status);
+        }
+        ByteRanges::Unsatisfiable => {
+            let head = head
+                .status(416)
+                .header("Content-Range", format!("bytes */{}", size));
+            return request.send(head);
+        }
+        ByteRanges::Partial(ranges) => ranges,
+    };
+
+    if let [range] = &ranges[..] {
+        let head = head
+            .status(206)
+            .header("Content-Type", mime)
+            .header("Content-Range", content_range(range, size))
+            .header("Content-Length", range.end - range.start);
+        let status = request.send_head(head)?;
+        copy_range(&mut reader, range, &mut request.stream())?;
+        return Ok(status);
+    }
+
+    let boundary = boundary();
+    let parts: Vec<String> = ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                boundary,
+                mime,
+                content_range(range, size)
+            )
+        })
+        .collect();
+    let closing = format!("\r\n--{}--\r\n", boundary);
+    let length = parts.iter().map(String::len).sum::<usize>() as u64
+        + ranges
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum::<u64>()
+        + closing.len() as u64;
+
+    let head = head
+        .status(206)
+        .header(
+            "Content-Type",
+            format!("multipart/byteranges; boundary={}", boundary),
+        )
+        .header("Content-Length", length);
+    let status = request.send_head(head)?;
+
+    let mut stream = request.stream();
+    for (part, range) in parts.iter().zip(&ranges) {
+        stream.write_all(part.as_bytes())?;
+        copy_range(&mut reader, range, &mut stream)?;
+    }
+    stream.write_all(closing.as_bytes())?;
+    Ok(status)
+}
+
+/// A strong entity tag for a file, from its size and modification time.
+fn entity_tag(metadata: &fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", metadata.len(), modified.as_nanos())
+}
+
+/// Whether the client's cached copy described by `If-None-Match`, or
+/// failing that `If-Modified-Since`, is still the version `head` describes.
+fn not_modified(request: &Request, head: &HttpResponse) -> bool {
+    if request.method != "GET" && request.method != "HEAD" {
+        return false;
+    }
+
+    let etag = head.headers().get("ETag").unwrap_or_default();
+    let mut tags = request
+        .headers()
+        .get_all("If-None-Match")
+        .flat_map(|tags| tags.split(','))
+        .map(str::trim)
+        .peekable();
+    if tags.peek().is_some() {
+        // entity tags are compared weakly here, ignoring any `W/` prefix
+        let opaque = |tag: &str| tag.trim_start_matches("W/").to_string();
+        return tags.any(|tag| tag == "*" || opaque(tag) == opaque(etag));
+    }
+
+    let since = request
+        .headers()
+        .get("If-Modified-Since")
+        .and_then(parse_http_date);
+    let modified = head
+        .headers()
+        .get("Last-Modified")
+        .and_then(parse_http_date);
+    matches!((since, modified), (Some(since), Some(modified)) if modified <= since)
+}
+
+/// Whether a `Range` header applies, which it does unless `If-Range` names
+/// a different version of the file than `head` describes. Entity tags must
+/// match exactly, and weak ones never do.
+fn if_range_matches(request: &Request, head: &HttpResponse) -> bool {
+    let Some(validator) = request.headers().get("If-Range").map(str::trim) else {
+        return true;
+    };
+    if validator.starts_with('"') {
+        return head.headers().get("ETag") == Some(validator);
     }
 
-    // attempt to copy the file
-    io::copy(&mut reader, &mut writer)?;
+    let modified = head
+        .headers()
+        .get("Last-Modified")
+        .and_then(parse_http_date);
+    modified.is_some() && parse_http_date(validator) == modified
+}
+
+/// Copies one range of a file to `writer`.
+fn copy_range(
+    reader: &mut (impl Read + Seek),
+    range: &Range<u64>,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    reader.seek(SeekFrom::Start(range.start))?;
+    let copied = io::copy(&mut reader.take(range.end - range.start), writer)?;
+    match copied == range.end - range.start {
+        true => Ok(()),
+        // the file shrank after the head was sent
+        false => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "file truncated while sending",
+        )),
+    }
+}
+
+/// A multipart boundary which is unlikely to appear in the file.
+fn boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("{:032x}", nanos ^ ((std::process::id() as u128) << 64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp dir holding `file.txt`,
+    /// `with space.txt` and `sub/index.html`, next to a `secret.txt` which
+    /// is outside it.
+    fn root(name: &str) -> PathBuf {
+        let base =
+            std::env::temp_dir().join(format!("rust-server-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let root = base.join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("file.txt"), "file").unwrap();
+        fs::write(root.join("with space.txt"), "space").unwrap();
+        fs::write(root.join("sub/index.html"), "index").unwrap();
+        fs::write(base.join("secret.txt"), "secret").unwrap();
+        root
+    }
+
+    #[test]
+    fn resolves_files_and_directories_under_the_root() {
+        let root = root("resolve");
+        let canonical = root.canonicalize().unwrap();
+        assert_eq!(
+            resolve_path(&root, "/file.txt"),
+            Some(canonical.join("file.txt"))
+        );
+        assert_eq!(
+            resolve_path(&root, "/./sub//index.html?v=1"),
+            Some(canonical.join("sub/index.html"))
+        );
+        assert_eq!(resolve_path(&root, "/sub/"), Some(canonical.join("sub")));
+        assert_eq!(resolve_path(&root, "/"), Some(canonical));
+    }
 
-    // response
-    Ok(200)
+    #[test]
+    fn resolves_percent_encoded_names_with_spaces() {
+        let root = root("spaces");
+        let expected = Some(root.canonicalize().unwrap().join("with space.txt"));
+        assert_eq!(resolve_path(&root, "/with%20space.txt"), expected);
+        assert_eq!(resolve_path(&root, "/with space.txt"), expected);
+    }
+
+    #[test]
+    fn refuses_parent_segments() {
+        let root = root("parent");
+        assert_eq!(resolve_path(&root, "/../secret.txt"), None);
+        assert_eq!(resolve_path(&root, "/sub/../file.txt"), None);
+        assert_eq!(resolve_path(&root, "/%2e%2e/secret.txt"), None);
+        assert_eq!(resolve_path(&root, "/%2E%2E%2fsecret.txt"), None);
+        assert_eq!(resolve_path(&root, "/sub/%2e%2e/%2e%2e/secret.txt"), None);
+    }
+
+    #[test]
+    fn refuses_backslash_and_nul_segments() {
+        let root = root("separators");
+        assert_eq!(resolve_path(&root, "/..\\secret.txt"), None);
+        assert_eq!(resolve_path(&root, "/%5c..%5csecret.txt"), None);
+        assert_eq!(resolve_path(&root, "/file.txt%00.html"), None);
+    }
+
+    #[test]
+    fn missing_files_resolve_to_none() {
+        let root = root("missing");
+        assert_eq!(resolve_path(&root, "/nope.txt"), None);
+        assert_eq!(resolve_path(&root, "/sub/nope/index.html"), None);
+        assert_eq!(resolve_path(&root.join("nope"), "/file.txt"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_symlinks_out_of_the_root() {
+        let root = root("symlink");
+        std::os::unix::fs::symlink(root.join("../secret.txt"), root.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(root.join(".."), root.join("up")).unwrap();
+        std::os::unix::fs::symlink(root.join("file.txt"), root.join("inside.txt")).unwrap();
+
+        assert_eq!(resolve_path(&root, "/link.txt"), None);
+        assert_eq!(resolve_path(&root, "/up/secret.txt"), None);
+        assert_eq!(
+            resolve_path(&root, "/inside.txt"),
+            Some(root.canonicalize().unwrap().join("file.txt"))
+        );
+    }
 }
@@ -0,0 +1,324 @@
+use crate::core::http::parser::{invalid, read_fields, read_line};
+use crate::core::http::HeaderMap;
+use crate::core::http::Limits;
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+
+static HTTP_CRLF: &[u8] = b"\r\n";
+
+/// Chunked Decoder
+///
+/// Wraps a reader positioned at the start of a chunked message body and
+/// yields the decoded payload. Once the last chunk has been read the
+/// trailer fields are available through `trailers()`.
+pub struct ChunkedDecoder<R: BufRead> {
+    reader: R,
+    remaining: u64,
+    trailers: Option<HeaderMap>,
+    limits: Limits,
+}
+
+impl<R: BufRead> ChunkedDecoder<R> {
+    /// Decodes the body from `reader`, reading trailer fields within the
+    /// header `limits`, as they're parsed like the header fields.
+    pub fn new(reader: R, limits: Limits) -> Self {
+        ChunkedDecoder {
+            reader,
+            remaining: 0,
+            trailers: None,
+            limits,
+        }
+    }
+
+    /// Trailer fields sent after the last chunk, `None` until the body is fully read.
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    pub fn into_trailers(self) -> HeaderMap {
+        self.trailers.unwrap_or_default()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.trailers.is_some()
+    }
+
+    /// Reads the next chunk-size line, ignoring any chunk extensions.
+    fn read_chunk_size(&mut self) -> io::Result<u64> {
+        let line = read_line(&mut self.reader)?.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "Connection closed in chunked body",
+            )
+        })?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        u64::from_str_radix(size, 16).map_err(|_| invalid(format!("Invalid chunk size: {}", size)))
+    }
+
+    /// Consumes the CRLF which terminates every chunk's data.
+    fn read_chunk_end(&mut self) -> io::Result<()> {
+        match read_line(&mut self.reader)? {
+            Some(line) if line.is_empty() => Ok(()),
+            _ => Err(invalid("Missing CRLF after chunk data")),
+        }
+    }
+}
+
+impl<R: BufRead> Read for ChunkedDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.is_done() || buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            self.remaining = self.read_chunk_size()?;
+            if self.remaining == 0 {
+                self.trailers = Some(read_fields(&mut self.reader, &self.limits)?);
+                return Ok(0);
+            }
+        }
+
+        let max = buf.len().min(self.remaining as usize);
+        let n = self.reader.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "Connection closed in chunk data",
+            ));
+        }
+
+        self.remaining -= n as u64;
+        if self.remaining == 0 {
+            self.read_chunk_end()?;
+        }
+
+        Ok(n)
+    }
+}
+
+/// Chunked Writer
+///
+/// Encodes everything written into it as HTTP chunks so a response can be
+/// streamed without knowing its length up front. Call `finish` to write the
+/// last chunk, otherwise the client will wait for more data.
+///
+/// HTTP/1.0 clients don't understand chunks, for them the writer passes the
+/// bytes through unchanged and the body ends when the connection closes.
+pub struct ChunkedWriter<W: Write> {
+    writer: W,
+    chunked: bool,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub fn new(writer: W) -> Self {
+        ChunkedWriter {
+            writer,
+            chunked: true,
+        }
+    }
+
+    /// A writer which doesn't encode chunks, for close-delimited bodies.
+    pub fn identity(writer: W) -> Self {
+        ChunkedWriter {
+            writer,
+            chunked: false,
+        }
+    }
+
+    pub fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+
+    /// Writes the terminating zero-length chunk.
+    pub fn finish(self) -> io::Result<W> {
+        self.finish_with_trailers(&HeaderMap::new())
+    }
+
+    /// Writes the terminating zero-length chunk followed by trailer fields,
+    /// trailers can't be sent on a close-delimited body and are dropped.
+    pub fn finish_with_trailers(mut self, trailers: &HeaderMap) -> io::Result<W> {
+        if !self.chunked {
+            self.writer.flush()?;
+            return Ok(self.writer);
+        }
+
+        self.writer.write_all(b"0\r\n")?;
+        for (key, value) in trailers.iter() {
+            write!(self.writer, "{}: {}\r\n", key, value)?;
+        }
+        self.writer.write_all(HTTP_CRLF)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.chunked {
+            return self.writer.write(buf);
+        }
+
+        // an empty chunk would terminate the body early
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.writer, "{:x}\r\n", buf.len())?;
+        self.writer.write_all(buf)?;
+        self.writer.write_all(HTTP_CRLF)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes a whole chunked body, returning it with the trailers.
+    fn decode(body: &[u8]) -> io::Result<(Vec<u8>, HeaderMap)> {
+        let mut decoder = ChunkedDecoder::new(body, Limits::default());
+        let mut decoded = Vec::new();
+        decoder.read_to_end(&mut decoded)?;
+        Ok((decoded, decoder.into_trailers()))
+    }
+
+    fn error(body: &[u8]) -> io::Error {
+        decode(body).unwrap_err()
+    }
+
+    #[test]
+    fn decodes_several_chunks() {
+        let (body, trailers) =
+            decode(b"5\r\nhello\r\n1\r\n \r\nA\r\n0123456789\r\n0\r\n\r\n").unwrap();
+        assert_eq!(body, b"hello 0123456789");
+        assert!(trailers.is_empty());
+    }
+
+    #[test]
+    fn leaves_the_reader_after_the_body() {
+        let mut reader: &[u8] = b"3\r\nabc\r\n0\r\n\r\nGET / HTTP/1.1\r\n";
+        let mut decoder = ChunkedDecoder::new(&mut reader, Limits::default());
+        let mut body = Vec::new();
+        decoder.read_to_end(&mut body).unwrap();
+        assert!(decoder.is_done());
+        assert_eq!(reader, b"GET / HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn ignores_chunk_extensions() {
+        let (body, _) =
+            decode(b"4;name=value\r\nwiki\r\n5 ; quoted=\"a;b\"\r\npedia\r\n0;last\r\n\r\n")
+                .unwrap();
+        assert_eq!(body, b"wikipedia");
+    }
+
+    #[test]
+    fn reads_trailers_after_the_last_chunk() {
+        let mut decoder = ChunkedDecoder::new(
+            &b"3\r\nabc\r\n0\r\nExpires: never\r\nX-Checksum: 42\r\n\r\n"[..],
+            Limits::default(),
+        );
+        let mut body = Vec::new();
+        assert!(decoder.trailers().is_none());
+        decoder.read_to_end(&mut body).unwrap();
+
+        let trailers = decoder.trailers().unwrap();
+        assert_eq!(trailers.get("expires"), Some("never"));
+        assert_eq!(trailers.get("X-Checksum"), Some("42"));
+        assert_eq!(trailers.len(), 2);
+    }
+
+    #[test]
+    fn refuses_trailers_over_the_limits() {
+        let limits = Limits {
+            max_header_count: 1,
+            ..Limits::default()
+        };
+        let mut decoder = ChunkedDecoder::new(&b"0\r\nA: 1\r\nB: 2\r\n\r\n"[..], limits);
+        assert!(decoder.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn refuses_a_bad_chunk_size() {
+        for body in [
+            &b"xyz\r\nabc\r\n0\r\n\r\n"[..],
+            b"-3\r\nabc\r\n0\r\n\r\n",
+            b"\r\n",
+        ] {
+            let error = error(body);
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert!(
+                error.to_string().starts_with("Invalid chunk size"),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_chunk_data_without_a_crlf() {
+        let error = error(b"3\r\nabcdef\r\n0\r\n\r\n");
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Missing CRLF after chunk data");
+    }
+
+    #[test]
+    fn fails_when_the_body_is_cut_short() {
+        assert_eq!(error(b"5\r\nab").kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(error(b"3\r\nabc\r\n").kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn writes_chunks_and_the_last_chunk() {
+        let mut writer = ChunkedWriter::new(Vec::new());
+        writer.write_all(b"hello").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(&[b'x'; 26]).unwrap();
+        let out = writer.finish().unwrap();
+
+        let mut expected = b"5\r\nhello\r\n1a\r\n".to_vec();
+        expected.extend([b'x'; 26]);
+        expected.extend(b"\r\n0\r\n\r\n");
+        assert_eq!(out, expected);
+        assert_eq!(
+            decode(&out).unwrap().0,
+            b"hello"
+                .iter()
+                .chain(&[b'x'; 26])
+                .copied()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn finishes_with_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("X-Checksum", "42");
+        trailers.insert("Server-Timing", "total;dur=12");
+
+        let mut writer = ChunkedWriter::new(Vec::new());
+        writer.write_all(b"abc").unwrap();
+        let out = writer.finish_with_trailers(&trailers).unwrap();
+        assert_eq!(
+            out,
+            b"3\r\nabc\r\n0\r\nX-Checksum: 42\r\nServer-Timing: total;dur=12\r\n\r\n"
+        );
+
+        let (body, decoded) = decode(&out).unwrap();
+        assert_eq!(body, b"abc");
+        assert_eq!(decoded.get("x-checksum"), Some("42"));
+    }
+
+    #[test]
+    fn identity_writer_passes_bytes_through() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("X-Checksum", "42");
+
+        let mut writer = ChunkedWriter::identity(Vec::new());
+        assert!(!writer.is_chunked());
+        writer.write_all(b"hello").unwrap();
+        assert_eq!(writer.finish_with_trailers(&trailers).unwrap(), b"hello");
+    }
+}
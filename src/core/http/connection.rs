@@ -0,0 +1,172 @@
+use crate::core::http::parser;
+use crate::core::http::Limits;
+use crate::core::http::Request;
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+/// The state of an idle connection between requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Readiness {
+    /// The next request has (at least partially) arrived.
+    Ready,
+    /// Nothing has been sent yet, the connection is still open.
+    Idle,
+    /// The peer closed the connection or it failed.
+    Closed,
+}
+
+/// A stream whose reads fail with `TimedOut` once a deadline has passed,
+/// however slowly the bytes trickle in before it.
+#[derive(Debug)]
+struct TimedStream {
+    stream: TcpStream,
+    deadline: Option<Instant>,
+}
+
+impl TimedStream {
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        // a timeout too long to represent is as good as none
+        self.deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        if self.deadline.is_none() {
+            self.stream.set_read_timeout(None)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for TimedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(ErrorKind::TimedOut, "Read timed out"));
+            }
+            self.stream.set_read_timeout(Some(remaining))?;
+        }
+        self.stream.read(buf)
+    }
+}
+
+/// Returns true if the error is a read timing out.
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// Connection
+///
+/// A persistent HTTP/1.x connection. Requests are read one after another
+/// from the same buffered stream, so any bytes which arrive early are kept
+/// for the next request rather than lost.
+///
+/// Reading a request is bounded by the `Limits`, errors carry the status
+/// code to answer with (see `HttpError::status_of`).
+#[derive(Debug)]
+pub struct Connection {
+    reader: BufReader<TimedStream>,
+    limits: Limits,
+    max_requests: usize,
+    served: usize,
+    last_active: Instant,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream, max_requests: usize, limits: Limits) -> Self {
+        let stream = TimedStream {
+            stream,
+            deadline: None,
+        };
+        Connection {
+            reader: BufReader::new(stream),
+            limits,
+            max_requests,
+            served: 0,
+            last_active: Instant::now(),
+        }
+    }
+
+    /// Reads the next request from the connection, blocking until it arrives.
+    /// Returns `None` if the peer closed the connection between requests, or
+    /// never started sending one before the header timeout.
+    pub fn next_request(&mut self) -> io::Result<Option<Request>> {
+        let limits = self.limits;
+        self.reader
+            .get_mut()
+            .set_timeout(Some(limits.header_timeout))?;
+
+        // nothing is owed to a client which hasn't started a request
+        match self.reader.fill_buf().map(|buffer| buffer.is_empty()) {
+            Ok(true) => return Ok(None),
+            Ok(false) => {}
+            Err(e) if is_timeout(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let head = match parser::read_head(&mut self.reader, &limits)? {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+
+        self.reader
+            .get_mut()
+            .set_timeout(Some(limits.body_timeout))?;
+        let (body, trailers) = parser::read_body(&mut self.reader, &head.headers, &limits)?;
+        self.reader.get_mut().set_timeout(None)?;
+        let stream = self.stream().try_clone()?;
+
+        self.served += 1;
+        self.last_active = Instant::now();
+
+        let mut request = Request::from_parts(head, stream, body, trailers);
+        let keep_alive = request.keep_alive() && self.served < self.max_requests;
+        request.set_keep_alive(keep_alive);
+        Ok(Some(request))
+    }
+
+    /// Checks without blocking whether the next request has started to arrive.
+    pub fn poll(&self) -> Readiness {
+        if !self.reader.buffer().is_empty() {
+            return Readiness::Ready;
+        }
+
+        let stream = self.stream();
+        if stream.set_nonblocking(true).is_err() {
+            return Readiness::Closed;
+        }
+
+        let readiness = match stream.peek(&mut [0u8; 1]) {
+            Ok(0) => Readiness::Closed,
+            Ok(_) => Readiness::Ready,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Readiness::Idle,
+            Err(_) => Readiness::Closed,
+        };
+
+        match stream.set_nonblocking(false) {
+            Ok(_) => readiness,
+            Err(_) => Readiness::Closed,
+        }
+    }
+
+    /// Resets the idle timer, e.g. when the connection is parked after a response.
+    pub fn touch(&mut self) {
+        self.last_active = Instant::now();
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        self.last_active.elapsed()
+    }
+
+    pub fn served(&self) -> usize {
+        self.served
+    }
+
+    pub fn stream(&self) -> &TcpStream {
+        &self.reader.get_ref().stream
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+}
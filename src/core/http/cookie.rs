@@ -0,0 +1,109 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Cookie
+///
+/// A cookie sent to the client with a `Set-Cookie` header.
+///
+/// ```
+/// use rust_server::core::http::Cookie;
+///
+/// let cookie = Cookie::new("session", "abc123").path("/").http_only();
+/// assert_eq!(cookie.to_string(), "session=abc123; Path=/; HttpOnly");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    same_site: Option<String>,
+    secure: bool,
+    http_only: bool,
+}
+
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            same_site: None,
+            secure: false,
+            http_only: false,
+        }
+    }
+
+    /// A cookie which tells the client to delete the cookie with this name.
+    pub fn expired(name: impl Into<String>) -> Self {
+        Cookie::new(name, "").max_age(Duration::ZERO)
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// One of `Strict`, `Lax` or `None`.
+    pub fn same_site(mut self, same_site: impl Into<String>) -> Self {
+        self.same_site = Some(same_site.into());
+        self
+    }
+
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    pub fn http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+/// Formats the cookie as the value of a `Set-Cookie` header.
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(same_site) = &self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        Ok(())
+    }
+}
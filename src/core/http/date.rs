@@ -0,0 +1,185 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+/// The last year an HTTP date is parsed for, dates have four digit years.
+static MAX_YEAR: i64 = 9999;
+static MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats a time as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+/// Times before the epoch are formatted as the epoch.
+///
+/// ```
+/// use rust_server::core::http::date::format_http_date;
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(784111777);
+/// assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+/// ```
+pub fn format_http_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let days = secs / 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Parses an HTTP date in any of the three formats HTTP allows: the
+/// preferred `Sun, 06 Nov 1994 08:49:37 GMT`, the obsolete RFC 850
+/// `Sunday, 06-Nov-94 08:49:37 GMT` and asctime `Sun Nov  6 08:49:37 1994`.
+/// The day name isn't checked against the date. Returns `None` for dates
+/// before 1970 or after the year 9999.
+pub fn parse_http_date(text: &str) -> Option<SystemTime> {
+    let (_, rest) = text.trim().split_once([',', ' '])?;
+    let parts: Vec<&str> = rest.split([' ', '-']).filter(|s| !s.is_empty()).collect();
+
+    let (day, month, year, time) = match parts[..] {
+        [day, month, year, time, "GMT"] => (day, month, year, time),
+        [month, day, time, year] => (day, month, year, time),
+        _ => return None,
+    };
+
+    let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+    let day: u32 = day.parse().ok().filter(|day| (1..=31).contains(day))?;
+    let year: i64 = match year.parse().ok()? {
+        // two digit RFC 850 years, read as the closest to the present
+        year @ 0..=69 => 2000 + year,
+        year @ 70..=99 => 1900 + year,
+        year => year,
+    };
+
+    let mut clock = time.splitn(3, ':').map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if hour > 23 || minute > 59 || second > 60 || !(1970..=MAX_YEAR).contains(&year) {
+        return None;
+    }
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days
+        .checked_mul(86400)?
+        .checked_add(hour * 3600 + minute * 60 + second)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// Converts days since the epoch to a (year, month, day) date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) date to days since the epoch.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = i64::from((month + 9) % 12);
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn parses_the_three_formats() {
+        let expected = at(784111777);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), expected);
+        assert_eq!(
+            parse_http_date("  Sun, 06 Nov 1994 08:49:37 GMT "),
+            expected
+        );
+    }
+
+    #[test]
+    fn reads_two_digit_years_as_the_closest_century() {
+        assert_eq!(parse_http_date("Thursday, 01-Jan-70 00:00:00 GMT"), at(0));
+        assert_eq!(
+            parse_http_date("Saturday, 01-Jan-00 00:00:00 GMT"),
+            at(946684800)
+        );
+    }
+
+    #[test]
+    fn round_trips_formatted_dates() {
+        for secs in [0, 951782400, 1709210096, 253402300799] {
+            let time = UNIX_EPOCH + Duration::from_secs(secs);
+            assert_eq!(parse_http_date(&format_http_date(time)), Some(time));
+        }
+        assert_eq!(
+            format_http_date(UNIX_EPOCH + Duration::from_secs(951782400)),
+            "Tue, 29 Feb 2000 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn refuses_out_of_range_fields() {
+        for date in [
+            "Sun, 00 Nov 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:60:00 GMT",
+            "Sun, 06 Nov 1994 08:49:61 GMT",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Sun, 06 Nov 1994 08:49:37 PST",
+            "Wed, 31 Dec 1969 23:59:59 GMT",
+            "06 Nov 1994",
+            "",
+        ] {
+            assert_eq!(parse_http_date(date), None, "{}", date);
+        }
+    }
+
+    #[test]
+    fn refuses_huge_years_without_overflowing() {
+        assert_eq!(
+            parse_http_date("Fri, 31 Dec 9999 23:59:59 GMT"),
+            at(253402300799)
+        );
+        for date in [
+            "Sat, 01 Jan 10000 00:00:00 GMT",
+            "Sun, 06 Nov 99999999999 08:49:37 GMT",
+            "Sun, 06 Nov 99999999999999999999999 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 99999999999",
+        ] {
+            assert_eq!(parse_http_date(date), None, "{}", date);
+        }
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 99999999999999999999:00:00 GMT"),
+            None
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use crate::core::data::deflate;
+
+/// A content coding named in `Accept-Encoding` and `Content-Encoding`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+    Deflate,
+    /// Only served from precompressed `.br` files, the server can't encode it.
+    Brotli,
+}
+
+impl ContentEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentEncoding::Identity => "identity",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+            ContentEncoding::Brotli => "br",
+        }
+    }
+
+    /// The extension of a precompressed sibling file, e.g. `app.js.gz`.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            ContentEncoding::Gzip => Some("gz"),
+            ContentEncoding::Brotli => Some("br"),
+            ContentEncoding::Identity | ContentEncoding::Deflate => None,
+        }
+    }
+
+    /// Whether the server can compress with this coding itself.
+    pub fn can_encode(&self) -> bool {
+        matches!(self, ContentEncoding::Gzip | ContentEncoding::Deflate)
+    }
+
+    /// Compresses data with this coding, or returns `None` if the server
+    /// can't encode it.
+    pub fn encode(&self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            ContentEncoding::Gzip => Some(deflate::gzip(data)),
+            ContentEncoding::Deflate => Some(deflate::zlib(data)),
+            ContentEncoding::Identity | ContentEncoding::Brotli => None,
+        }
+    }
+
+    /// Picks the coding the client prefers out of those `offered`, by the
+    /// quality values in its `Accept-Encoding` header. Ties go to the first
+    /// offered, and `Identity` is returned if none of them are acceptable.
+    ///
+    /// ```
+    /// use rust_server::core::http::ContentEncoding::{self, *};
+    ///
+    /// let offered = [Brotli, Gzip];
+    /// assert_eq!(ContentEncoding::negotiate("gzip, br", &offered), Brotli);
+    /// assert_eq!(ContentEncoding::negotiate("br;q=0.5, gzip", &offered), Gzip);
+    /// assert_eq!(ContentEncoding::negotiate("*;q=0.1, br;q=0", &offered), Gzip);
+    /// assert_eq!(ContentEncoding::negotiate("deflate", &offered), Identity);
+    /// ```
+    pub fn negotiate(accept_encoding: &str, offered: &[ContentEncoding]) -> ContentEncoding {
+        let preferences: Vec<(&str, f32)> = accept_encoding
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';').map(str::trim);
+                let coding = parts.next().filter(|coding| !coding.is_empty())?;
+                let quality = parts
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+                Some((coding, quality))
+            })
+            .collect();
+
+        let quality = |name: &str| {
+            let find = |coding: &str| {
+                preferences
+                    .iter()
+                    .find(|(candidate, _)| candidate.eq_ignore_ascii_case(coding))
+                    .map(|(_, quality)| *quality)
+            };
+            let alias = (name == "gzip").then(|| find("x-gzip")).flatten();
+            find(name).or(alias).or_else(|| find("*")).unwrap_or(0.0)
+        };
+
+        let mut best = (ContentEncoding::Identity, 0.0);
+        for &encoding in offered {
+            let quality = quality(encoding.as_str());
+            if quality > best.1 {
+                best = (encoding, quality);
+            }
+        }
+        best.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContentEncoding::{self, *};
+
+    const OFFERED: [ContentEncoding; 3] = [Brotli, Gzip, Deflate];
+
+    fn negotiate(accept_encoding: &str) -> ContentEncoding {
+        ContentEncoding::negotiate(accept_encoding, &OFFERED)
+    }
+
+    #[test]
+    fn picks_the_highest_quality() {
+        assert_eq!(negotiate("gzip;q=0.5, deflate;q=0.8"), Deflate);
+        assert_eq!(negotiate("gzip; q=1.0, br;q=0.9"), Gzip);
+        assert_eq!(negotiate("deflate, gzip;q=1"), Gzip);
+        assert_eq!(negotiate("GZIP"), Gzip);
+        assert_eq!(negotiate("x-gzip"), Gzip);
+    }
+
+    #[test]
+    fn breaks_ties_by_the_offered_order() {
+        assert_eq!(negotiate("deflate, gzip, br"), Brotli);
+        assert_eq!(negotiate("*"), Brotli);
+        assert_eq!(
+            ContentEncoding::negotiate("gzip, deflate", &[Deflate, Gzip]),
+            Deflate
+        );
+    }
+
+    #[test]
+    fn never_picks_a_coding_with_zero_quality() {
+        assert_eq!(negotiate("br;q=0, gzip;q=0"), Identity);
+        assert_eq!(negotiate("br;q=0, *;q=0.2"), Gzip);
+        assert_eq!(negotiate("*;q=0"), Identity);
+        assert_eq!(negotiate("gzip;q=0.000"), Identity);
+    }
+
+    #[test]
+    fn ignores_identity_and_malformed_entries() {
+        // identity can't be refused when nothing else is acceptable, the
+        // body is then sent as is rather than answered with an error
+        assert_eq!(negotiate("identity;q=0, gzip"), Gzip);
+        assert_eq!(negotiate("identity;q=0"), Identity);
+        assert_eq!(negotiate("gzip;q=high, deflate"), Deflate);
+        assert_eq!(negotiate(" , ;q=1, deflate"), Deflate);
+        assert_eq!(negotiate(""), Identity);
+        assert_eq!(ContentEncoding::negotiate("gzip", &[]), Identity);
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+use std::io::{self, ErrorKind};
+
+/// HTTP Error
+///
+/// A request which can't be served and should be answered with a specific
+/// status code, e.g. a header section over the configured limit. Carried
+/// inside an `io::Error` so the parser keeps its `io::Result` signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    /// Creates an `io::Error` carrying the status code.
+    pub fn io(status: u16, message: impl Into<String>) -> io::Error {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            HttpError {
+                status,
+                message: message.into(),
+            },
+        )
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The status code to answer a failed read with: the one carried by an
+    /// `HttpError`, `400` for other malformed requests and `408` for timeouts.
+    /// Returns `None` when the connection itself failed and nothing can be sent.
+    pub fn status_of(error: &io::Error) -> Option<u16> {
+        if let Some(error) = error.get_ref().and_then(|e| e.downcast_ref::<HttpError>()) {
+            return Some(error.status);
+        }
+        match error.kind() {
+            ErrorKind::InvalidData => Some(400),
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => Some(408),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl std::error::Error for HttpError {}
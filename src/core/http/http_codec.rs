@@ -1,61 +1,219 @@
-use std::io::{self, Read, Write};
+use std::io::{self, Write};
 
-use crate::core::get_mime_type;
+use crate::core::http::status;
+use crate::core::http::{Cookie, HeaderMap, Version};
 
 pub type Bytes = Vec<u8>;
-pub type Headers = Vec<(String, String)>;
-pub type Response = Result<u8, io::Error>;
+pub type Headers = HeaderMap;
+pub type Response = Result<u16, io::Error>;
 
 static HTTP_CRLF: &[u8] = b"\r\n";
-static HTTP_VERSION: &[u8] = b"HTTP/1.1";
 
+/// HTTP Response
+///
+/// A fully buffered response built with chained calls, then passed
+/// to `Request::send`. The `Content-Length` is filled in when encoded.
+///
+/// ```
+/// use rust_server::core::http::HttpResponse;
+///
+/// let response = HttpResponse::ok()
+///     .header("Cache-Control", "no-store")
+///     .json(r#"{ "ok": true }"#);
+///
+/// assert_eq!(response.status_code(), 200);
+/// assert_eq!(response.headers().get("content-type"), Some("application/json"));
+/// ```
+#[derive(Clone, Debug)]
 pub struct HttpResponse {
+    version: Version,
     status: u16,
     status_text: String,
     headers: Headers,
     body: Bytes,
 }
 
-// impl HttpResponse {
-//     pub fn new(status: u16, status_text: &str, headers: Headers, body: Bytes) -> Self {
-//         HttpResponse {
-//             status,
-//             status_text: status_text.to_string(),
-//             headers,
-//             body,
-//         }
-//     }
-// }
-
-/// Create a response from a file
-pub fn static_file(file_path: &str) -> HttpResponse {
-    let public_file_path = format!("src/public{}", file_path);
-
-    let file = match std::fs::File::open(&public_file_path) {
-        Ok(file) => file,
-        Err(e) => {
-            eprintln!("[http] {} error: {}", public_file_path, e);
-            return HttpResponse {
-                status: 404,
-                status_text: "Not Found".to_string(),
-                headers: vec![("Content-Type".to_string(), "text/html".to_string())],
-                body: format!("File not found: {}", e).as_bytes().to_vec(),
-            };
+impl HttpResponse {
+    /// Create an empty response with the given status code.
+    pub fn new(status: u16) -> Self {
+        HttpResponse {
+            version: Version::default(),
+            status,
+            status_text: status::reason_phrase(status).to_string(),
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
         }
-    };
+    }
+
+    pub fn ok() -> Self {
+        Self::new(200)
+    }
+
+    pub fn created() -> Self {
+        Self::new(201)
+    }
+
+    pub fn no_content() -> Self {
+        Self::new(204)
+    }
+
+    pub fn bad_request() -> Self {
+        Self::new(400)
+    }
 
-    let mime = get_mime_type(&public_file_path);
-    let metadata = file.metadata().unwrap();
-    let bytes = file.bytes().map(|b| b.unwrap()).collect::<Vec<u8>>();
+    pub fn not_found() -> Self {
+        Self::new(404)
+    }
+
+    pub fn internal_error() -> Self {
+        Self::new(500)
+    }
+
+    /// Create a redirect to the given location, e.g. `301`, `302`, `307` or `308`.
+    pub fn redirect(status: u16, location: &str) -> Self {
+        Self::new(status).header("Location", location)
+    }
+
+    /// Sets the status code along with its standard reason phrase.
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self.status_text = status::reason_phrase(status).to_string();
+        self
+    }
+
+    /// Sets the protocol version written on the status line,
+    /// `Request::send` sets this to match the request.
+    pub fn version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Overrides the reason phrase sent after the status code.
+    pub fn status_text(mut self, status_text: &str) -> Self {
+        self.status_text = status_text.to_string();
+        self
+    }
+
+    /// Sets a header, replacing any previous values for the same field.
+    pub fn header(mut self, key: &str, value: impl ToString) -> Self {
+        self.headers.insert(key, value.to_string());
+        self
+    }
+
+    /// Adds a header, keeping any previous values for the same field.
+    pub fn append_header(mut self, key: &str, value: impl ToString) -> Self {
+        self.headers.append(key, value.to_string());
+        self
+    }
+
+    /// Adds a `Set-Cookie` header.
+    pub fn cookie(self, cookie: Cookie) -> Self {
+        self.append_header("Set-Cookie", cookie)
+    }
+
+    /// Sets the body and its `Content-Type`.
+    pub fn body(mut self, content_type: &str, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self.header("Content-Type", content_type)
+    }
+
+    /// Sets a raw body, defaulting to `application/octet-stream` unless a
+    /// `Content-Type` was already set.
+    pub fn bytes(mut self, body: impl Into<Bytes>) -> Self {
+        if !self.headers.contains("Content-Type") {
+            self.headers
+                .insert("Content-Type", "application/octet-stream");
+        }
+        self.body = body.into();
+        self
+    }
+
+    /// Sets a pre-serialized JSON body.
+    pub fn json(self, json: impl Into<String>) -> Self {
+        self.body("application/json", json.into())
+    }
+
+    pub fn text(self, text: impl Into<String>) -> Self {
+        self.body("text/plain; charset=utf-8", text.into())
+    }
 
-    HttpResponse {
-        status: 200,
-        status_text: "OK".to_string(),
-        headers: vec![
-            ("Content-Type".to_string(), mime),
-            ("Content-Length".to_string(), metadata.len().to_string()),
-        ],
-        body: bytes,
+    pub fn html(self, html: impl Into<String>) -> Self {
+        self.body("text/html; charset=utf-8", html.into())
+    }
+
+    #[inline]
+    pub fn protocol_version(&self) -> Version {
+        self.version
+    }
+
+    #[inline]
+    pub fn status_code(&self) -> u16 {
+        self.status
+    }
+
+    #[inline]
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    #[inline]
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    /// The body, for middleware which transforms it after the handler,
+    /// e.g. to compress it. `Content-Length` is filled in when encoded.
+    #[inline]
+    pub fn body_mut(&mut self) -> &mut Bytes {
+        &mut self.body
+    }
+
+    #[inline]
+    pub fn body_bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Serializes the status line and headers followed by the empty line
+    /// which ends the head, adding `Content-Length` if given and not set.
+    fn head_bytes(&self, content_length: Option<usize>) -> Bytes {
+        let mut head = Vec::with_capacity(128);
+        head.extend_from_slice(self.version.as_str().as_bytes());
+        head.extend_from_slice(format!(" {} {}", self.status, self.status_text).as_bytes());
+        head.extend_from_slice(HTTP_CRLF);
+
+        for (key, value) in self.headers.iter() {
+            head.extend_from_slice(key.as_bytes());
+            head.extend_from_slice(b": ");
+            head.extend_from_slice(value.as_bytes());
+            head.extend_from_slice(HTTP_CRLF);
+        }
+
+        if let Some(length) = content_length {
+            if !status::is_bodyless(self.status) && !self.headers.contains("Content-Length") {
+                head.extend_from_slice(format!("Content-Length: {}", length).as_bytes());
+                head.extend_from_slice(HTTP_CRLF);
+            }
+        }
+
+        head.extend_from_slice(HTTP_CRLF);
+        head
+    }
+
+    /// Encode only the status line and headers, for responses whose body
+    /// is streamed separately. The headers must frame the body themselves
+    /// (`Content-Length` or `Transfer-Encoding`) or the connection must close.
+    pub fn encode_head_to(&self, writer: &mut impl Write) -> io::Result<usize> {
+        let head = self.head_bytes(None);
+        writer.write_all(&head)?;
+        Ok(head.len())
+    }
+
+    /// Encode the status line and headers with the `Content-Length` the body
+    /// would have, but not the body, for answering `HEAD` requests.
+    pub fn encode_without_body_to(&self, writer: &mut impl Write) -> io::Result<usize> {
+        let head = self.head_bytes(Some(self.body.len()));
+        writer.write_all(&head)?;
+        Ok(head.len())
     }
 }
 
@@ -68,36 +226,16 @@ pub trait HttpCodec {
 }
 
 impl HttpCodec for HttpResponse {
-    /// Encode the response into a writer.
+    /// Encode the response into a writer. Any body set on a `1xx`, `204`
+    /// or `304` response is left out, since the client won't read one.
     fn encode_to(&self, writer: &mut impl Write) -> io::Result<usize> {
-        let mut written = 0;
-        written += writer.write(HTTP_VERSION)?;
-
-        // small stack frame for status code
-        let status_str = self.status.to_string();
-        let status_bytes = status_str.as_bytes();
-
-        // write status code
-        written += writer.write(status_bytes)?;
-        written += writer.write(b" ")?;
-
-        // write status text
-        written += writer.write(self.status_text.as_bytes())?;
-        written += writer.write(HTTP_CRLF)?;
-
-        // write headers
-        for (key, value) in &self.headers {
-            written += writer.write(key.as_bytes())?;
-            written += writer.write(b": ")?;
-            written += writer.write(value.as_bytes())?;
-            written += writer.write(HTTP_CRLF)?;
+        if status::is_bodyless(self.status) {
+            return self.encode_without_body_to(writer);
         }
-
-        // write body
-        written += writer.write(HTTP_CRLF)?;
-        written += writer.write(&self.body)?;
-
-        Ok(written)
+        let head = self.head_bytes(Some(self.body.len()));
+        writer.write_all(&head)?;
+        writer.write_all(&self.body)?;
+        Ok(head.len() + self.body.len())
     }
 
     // /// Encode the response into a byte buffer.
@@ -137,3 +275,33 @@ impl HttpCodec for HttpResponse {
     //     buffer.extend_from_slice(&self.body);
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(response: HttpResponse) -> String {
+        let mut out = Vec::new();
+        let written = response.encode_to(&mut out).unwrap();
+        assert_eq!(written, out.len());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn encodes_the_body_with_its_length() {
+        let out = encode(HttpResponse::ok().text("hello"));
+        assert!(out.starts_with("HTTP/1.1 200 OK\r\n"), "{}", out);
+        assert!(out.contains("Content-Length: 5\r\n"), "{}", out);
+        assert!(out.ends_with("\r\n\r\nhello"), "{:?}", out);
+    }
+
+    #[test]
+    fn leaves_out_the_body_of_bodyless_statuses() {
+        for status in [100, 103, 204, 304] {
+            let out = encode(HttpResponse::new(status).text("ignored"));
+            assert!(out.ends_with("\r\n\r\n"), "{:?}", out);
+            assert!(!out.contains("ignored"), "{:?}", out);
+            assert!(!out.contains("Content-Length"), "{:?}", out);
+        }
+    }
+}
@@ -3,9 +3,13 @@
 /// Allow conversion from `&str` to Method
 ///
 /// ```
-/// fn route(method: impl Into<RouteMethod>) {
-///    let method = method.into();
+/// use rust_server::core::Method;
+///
+/// fn route(method: impl Into<Method>) -> Method {
+///    method.into()
 /// }
+///
+/// assert_eq!(route("get"), Method::GET);
 /// ```
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub enum Method {
@@ -18,18 +22,32 @@ pub enum Method {
 }
 
 impl Method {
-    pub fn from_str(s: &str) -> Self {
-        match s.to_uppercase().as_str() {
-            "GET" => Self::GET,
-            "POST" => Self::POST,
-            "PUT" => Self::PUT,
-            "PATCH" => Self::PATCH,
-            "DELETE" => Self::DELETE,
-            _ => Self::Custom(s.to_string()),
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::GET => "GET",
+            Self::POST => "POST",
+            Self::PUT => "PUT",
+            Self::PATCH => "PATCH",
+            Self::DELETE => "DELETE",
+            Self::Custom(method) => method,
         }
     }
 }
 
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Method {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(s))
+    }
+}
+
 impl From<&str> for Method {
     fn from(s: &str) -> Self {
         match s.to_uppercase().as_str() {
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+/// Limits
+///
+/// How long a client may take to send a request and how large it may be.
+/// Requests over a limit are answered with `408 Request Timeout`,
+/// `413 Content Too Large`, `414 URI Too Long` or
+/// `431 Request Header Fields Too Large` and the connection is closed.
+///
+/// ```
+/// use rust_server::core::http::Limits;
+/// use std::time::Duration;
+///
+/// let limits = Limits {
+///     max_body_size: 64 * 1024,
+///     header_timeout: Duration::from_secs(5),
+///     ..Limits::default()
+/// };
+/// assert_eq!(limits.max_header_count, 100);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Time allowed for the request line and headers, starting when the
+    /// worker begins waiting for the request.
+    pub header_timeout: Duration,
+    /// Time allowed for the whole body once the headers have arrived.
+    pub body_timeout: Duration,
+    /// Time allowed for each write of the response.
+    pub write_timeout: Duration,
+    /// Maximum length of the request line, including the uri.
+    pub max_request_line: usize,
+    /// Maximum number of header fields.
+    pub max_header_count: usize,
+    /// Maximum size of the header section in bytes.
+    pub max_header_size: usize,
+    /// Maximum size of the body in bytes, after removing chunked framing.
+    pub max_body_size: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            header_timeout: Duration::from_secs(10),
+            body_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            max_request_line: 8 * 1024,
+            max_header_count: 100,
+            max_header_size: 64 * 1024,
+            max_body_size: 10 * 1024 * 1024,
+        }
+    }
+}
@@ -1,7 +1,31 @@
+pub mod chunked;
+pub mod connection;
+pub mod cookie;
+pub mod date;
+pub mod encoding;
+pub mod error;
+pub mod headers;
 pub mod http_codec;
 pub mod http_method;
+pub mod limits;
+pub mod parser;
+pub mod range;
 pub mod request;
+pub mod status;
+pub mod version;
+pub use chunked::ChunkedDecoder;
+pub use chunked::ChunkedWriter;
+pub use connection::Connection;
+pub use connection::Readiness;
+pub use cookie::Cookie;
+pub use encoding::ContentEncoding;
+pub use error::HttpError;
+pub use headers::HeaderMap;
 pub use http_codec::HttpCodec;
+pub use http_codec::HttpResponse;
 pub use http_codec::Response;
 pub use http_method::Method;
+pub use limits::Limits;
+pub use range::ByteRanges;
 pub use request::Request;
+pub use version::Version;
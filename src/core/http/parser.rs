@@ -0,0 +1,465 @@
+use crate::core::http::ChunkedDecoder;
+use crate::core::http::HeaderMap;
+use crate::core::http::HttpError;
+use crate::core::http::Limits;
+use std::io::{self, BufRead, ErrorKind, Read};
+
+/// Maximum length of a chunk size line
+const MAX_LINE_SIZE: usize = 8 * 1024;
+
+/// Request Head
+///
+/// The request line and header fields of an HTTP/1.x request,
+/// everything before the body.
+#[derive(Debug, Clone)]
+pub struct RequestHead {
+    pub method: String,
+    pub uri: String,
+    pub protocol: String,
+    pub headers: HeaderMap,
+}
+
+pub(crate) fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.into())
+}
+
+/// Reads a single CRLF (or bare LF) terminated line without the line ending.
+/// Returns `None` if the reader is at EOF before any bytes were read.
+pub(crate) fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    read_line_within(reader, MAX_LINE_SIZE, 400)
+}
+
+/// Like `read_line`, but lines longer than `max` bytes (excluding the line
+/// ending) are refused with the given status code.
+fn read_line_within(
+    reader: &mut impl BufRead,
+    max: usize,
+    status: u16,
+) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let n = reader
+        .by_ref()
+        .take(max as u64 + 2)
+        .read_until(b'\n', &mut line)?;
+
+    if n == 0 {
+        return Ok(None);
+    }
+
+    if line.last() != Some(&b'\n') {
+        return Err(match n > max {
+            true => HttpError::io(status, "Line exceeds maximum size"),
+            false => io::Error::new(ErrorKind::UnexpectedEof, "Connection closed mid-line"),
+        });
+    }
+
+    let ending = match line.ends_with(b"\r\n") {
+        true => 2,
+        false => 1,
+    };
+    if line.len() - ending > max {
+        return Err(HttpError::io(status, "Line exceeds maximum size"));
+    }
+
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| invalid(format!("Invalid UTF-8: {}", e)))
+}
+
+/// Parses the request line into the method, uri, and protocol.
+fn parse_request_line(line: &str) -> io::Result<(String, String, String)> {
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(uri), Some(protocol), None) => {
+            if !protocol.starts_with("HTTP/") {
+                return Err(invalid(format!("Unsupported protocol: {}", protocol)));
+            }
+            Ok((method.to_string(), uri.to_string(), protocol.to_string()))
+        }
+        _ => Err(invalid("Invalid request line: missing required parts")),
+    }
+}
+
+/// Reads header fields up to and including the empty line which terminates them.
+/// Used for both the request head and chunked trailers, a header section over
+/// the limits is refused with `431 Request Header Fields Too Large`.
+pub(crate) fn read_fields(reader: &mut impl BufRead, limits: &Limits) -> io::Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    let mut size = 0;
+
+    loop {
+        let remaining = limits.max_header_size.saturating_sub(size);
+        let line = read_line_within(reader, remaining, 431)?.ok_or_else(|| {
+            io::Error::new(ErrorKind::UnexpectedEof, "Connection closed in headers")
+        })?;
+
+        if line.is_empty() {
+            return Ok(headers);
+        }
+
+        size += line.len();
+        if headers.len() >= limits.max_header_count {
+            return Err(HttpError::io(431, "Too many header fields"));
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid(format!("Invalid header line: {}", line)))?;
+
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(invalid(format!("Invalid header name: {:?}", name)));
+        }
+
+        headers.append(name, value.trim());
+    }
+}
+
+/// Reads the request line and header fields from the reader, consuming
+/// exactly the bytes of the head and leaving the body in the reader.
+///
+/// Returns `None` if the peer closed the connection before sending anything.
+/// A request line longer than the limit is refused with `414 URI Too Long`.
+pub fn read_head(reader: &mut impl BufRead, limits: &Limits) -> io::Result<Option<RequestHead>> {
+    // skip any empty lines preceding the request line (RFC 9112 2.2)
+    let request_line = loop {
+        match read_line_within(reader, limits.max_request_line, 414)? {
+            None => return Ok(None),
+            Some(line) if line.is_empty() => continue,
+            Some(line) => break line,
+        }
+    };
+
+    let (method, uri, protocol) = parse_request_line(&request_line)?;
+    let headers = read_fields(reader, limits)?;
+
+    Ok(Some(RequestHead {
+        method,
+        uri,
+        protocol,
+        headers,
+    }))
+}
+
+/// Returns the declared `Content-Length`, rejecting malformed or conflicting values.
+pub fn content_length(headers: &HeaderMap) -> io::Result<Option<u64>> {
+    let mut length = None;
+    for value in headers.get_all("Content-Length").flat_map(|v| v.split(',')) {
+        // only digits, `parse` would also take a leading `+`
+        let value = value.trim();
+        let parsed = Some(value)
+            .filter(|value| value.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|value| value.parse::<u64>().ok())
+            .ok_or_else(|| invalid(format!("Invalid Content-Length: {}", value)))?;
+        match length {
+            Some(existing) if existing != parsed => {
+                return Err(invalid("Conflicting Content-Length values"));
+            }
+            _ => length = Some(parsed),
+        }
+    }
+    Ok(length)
+}
+
+/// Returns true if the message body is framed with chunked transfer-encoding.
+/// Any other transfer-coding is rejected since the length could not be determined.
+pub fn is_chunked(headers: &HeaderMap) -> io::Result<bool> {
+    let mut codings = headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty());
+
+    match (codings.next(), codings.next()) {
+        (None, _) => Ok(false),
+        (Some(coding), None) if coding.eq_ignore_ascii_case("chunked") => Ok(true),
+        (Some(coding), _) => Err(invalid(format!(
+            "Unsupported Transfer-Encoding: {}",
+            coding
+        ))),
+    }
+}
+
+/// Reads the message body which follows the head, as framed by the headers,
+/// along with any trailer fields sent after a chunked body.
+/// Requests with neither `Transfer-Encoding` nor `Content-Length` have no body.
+/// A body larger than the limit is refused with `413 Content Too Large`.
+pub fn read_body(
+    reader: &mut impl BufRead,
+    headers: &HeaderMap,
+    limits: &Limits,
+) -> io::Result<(Option<Vec<u8>>, HeaderMap)> {
+    let too_large = || HttpError::io(413, "Body exceeds maximum size");
+
+    // transfer-encoding overrides content-length (RFC 9112 6.3)
+    if is_chunked(headers)? {
+        let mut decoder = ChunkedDecoder::new(reader, *limits);
+        let mut body = Vec::new();
+        (&mut decoder)
+            .take(limits.max_body_size + 1)
+            .read_to_end(&mut body)?;
+        if body.len() as u64 > limits.max_body_size {
+            return Err(too_large());
+        }
+        return Ok((Some(body), decoder.into_trailers()));
+    }
+
+    let length = match content_length(headers)? {
+        Some(length) if length > limits.max_body_size => return Err(too_large()),
+        Some(length) => length,
+        None => return Ok((None, HeaderMap::new())),
+    };
+
+    let mut body = Vec::with_capacity(length.min(MAX_LINE_SIZE as u64) as usize);
+    reader.by_ref().take(length).read_to_end(&mut body)?;
+
+    if (body.len() as u64) < length {
+        return Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("Body ended after {} of {} bytes", body.len(), length),
+        ));
+    }
+
+    Ok((Some(body), HeaderMap::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head(request: &[u8]) -> io::Result<Option<RequestHead>> {
+        read_head(&mut &request[..], &Limits::default())
+    }
+
+    fn fields(fields: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for field in fields {
+            let (name, value) = field.split_once(": ").unwrap();
+            headers.append(name, value);
+        }
+        headers
+    }
+
+    fn body(headers: &[&str], body: &[u8], limits: &Limits) -> io::Result<Option<Vec<u8>>> {
+        read_body(&mut &body[..], &fields(headers), limits).map(|(body, _)| body)
+    }
+
+    #[test]
+    fn reads_the_head_and_leaves_the_body() {
+        let mut reader: &[u8] =
+            b"\r\nPOST /form?a=1 HTTP/1.1\r\nHost: test\r\nAccept: a\r\nAccept:  b \r\n\r\nbody";
+        let head = read_head(&mut reader, &Limits::default()).unwrap().unwrap();
+        assert_eq!(head.method, "POST");
+        assert_eq!(head.uri, "/form?a=1");
+        assert_eq!(head.protocol, "HTTP/1.1");
+        assert_eq!(head.headers.get("host"), Some("test"));
+        assert_eq!(
+            head.headers.get_all("Accept").collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert_eq!(reader, b"body");
+    }
+
+    #[test]
+    fn accepts_bare_lf_line_endings() {
+        let mut reader: &[u8] = b"GET / HTTP/1.0\nHost: test\r\nX-Bare: lf\n\nrest";
+        let head = read_head(&mut reader, &Limits::default()).unwrap().unwrap();
+        assert_eq!(head.protocol, "HTTP/1.0");
+        assert_eq!(head.headers.get("X-Bare"), Some("lf"));
+        assert_eq!(reader, b"rest");
+    }
+
+    #[test]
+    fn returns_none_at_eof_and_fails_mid_head() {
+        assert!(head(b"").unwrap().is_none());
+        assert!(head(b"\r\n\r\n").unwrap().is_none());
+        assert_eq!(
+            head(b"GET / HTTP/1.1\r\nHost: te").unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            head(b"GET / HTTP/1.1\r\nHost: test\r\n")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn refuses_a_malformed_request_line() {
+        for request in [
+            &b"GET /\r\n\r\n"[..],
+            b"GET / HTTP/1.1 extra\r\n\r\n",
+            b"GET / FTP/1.0\r\n\r\n",
+            b"GET /\xff HTTP/1.1\r\n\r\n",
+        ] {
+            let error = head(request).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{:?}", request);
+            assert_eq!(HttpError::status_of(&error), Some(400));
+        }
+    }
+
+    #[test]
+    fn refuses_malformed_and_folded_fields() {
+        let limits = Limits::default();
+        for section in [
+            &b"No colon\r\n\r\n"[..],
+            b": empty name\r\n\r\n",
+            b"Bad Name: value\r\n\r\n",
+            b"Name : value\r\n\r\n",
+            // obs-fold continuation lines are refused rather than unfolded (RFC 9112 5.2)
+            b"X-Folded: first\r\n second\r\n\r\n",
+            b"X-Folded: first\r\n\tsecond: line\r\n\r\n",
+        ] {
+            let error = read_fields(&mut &section[..], &limits).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{:?}", section);
+        }
+    }
+
+    #[test]
+    fn refuses_a_request_line_over_the_limit() {
+        let limits = Limits {
+            max_request_line: 16,
+            ..Limits::default()
+        };
+        let fits = b"GET /12345 HTTP/1.1\r\n\r\n";
+        assert!(read_head(&mut &fits[..], &Limits::default()).is_ok());
+
+        let exact = b"GET /1 HTTP/1.1\r\n\r\n";
+        assert!(read_head(&mut &exact[..], &limits).unwrap().is_some());
+
+        for request in [&fits[..], b"GET /123456789012345678901234 HTTP/1.1"] {
+            let error = read_head(&mut &request[..], &limits).unwrap_err();
+            assert_eq!(HttpError::status_of(&error), Some(414));
+        }
+    }
+
+    #[test]
+    fn refuses_headers_over_the_limits() {
+        let limits = Limits {
+            max_header_count: 2,
+            max_header_size: 20,
+            ..Limits::default()
+        };
+        let read = |section: &[u8]| read_fields(&mut &section[..], &limits);
+
+        assert_eq!(read(b"A: 1\r\nB: 2\r\n\r\n").unwrap().len(), 2);
+        let error = read(b"A: 1\r\nB: 2\r\nC: 3\r\n\r\n").unwrap_err();
+        assert_eq!(HttpError::status_of(&error), Some(431));
+
+        // the size counts every line, so two fields can't sneak past the limit
+        assert!(read(b"Name: 0123456789abcd\r\n\r\n").is_ok());
+        let error = read(b"Name: 0123456789abcde\r\n\r\n").unwrap_err();
+        assert_eq!(HttpError::status_of(&error), Some(431));
+        let error = read(b"A: 01234567\r\nB: 01234567\r\n\r\n").unwrap_err();
+        assert_eq!(HttpError::status_of(&error), Some(431));
+    }
+
+    #[test]
+    fn reads_the_content_length() {
+        assert_eq!(content_length(&fields(&[])).unwrap(), None);
+        assert_eq!(
+            content_length(&fields(&["Content-Length: 42"])).unwrap(),
+            Some(42)
+        );
+        // repeated values are accepted as long as they agree (RFC 9110 8.6)
+        assert_eq!(
+            content_length(&fields(&["Content-Length: 7, 7", "content-length: 7"])).unwrap(),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn refuses_a_conflicting_or_invalid_content_length() {
+        for headers in [
+            &["Content-Length: 5", "Content-Length: 6"][..],
+            &["Content-Length: 5, 6"],
+            &["Content-Length: -1"],
+            &["Content-Length: +5"],
+            &["Content-Length: 0x10"],
+            &["Content-Length: "],
+            &["Content-Length: 99999999999999999999"],
+        ] {
+            let error = content_length(&fields(headers)).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{:?}", headers);
+        }
+    }
+
+    #[test]
+    fn only_accepts_chunked_transfer_encoding() {
+        assert!(!is_chunked(&fields(&[])).unwrap());
+        assert!(is_chunked(&fields(&["Transfer-Encoding: chunked"])).unwrap());
+        assert!(is_chunked(&fields(&["Transfer-Encoding: Chunked, "])).unwrap());
+        for headers in [
+            &["Transfer-Encoding: gzip"][..],
+            &["Transfer-Encoding: gzip, chunked"],
+            &["Transfer-Encoding: chunked", "Transfer-Encoding: chunked"],
+        ] {
+            let error = is_chunked(&fields(headers)).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{:?}", headers);
+        }
+    }
+
+    #[test]
+    fn reads_a_body_framed_by_content_length() {
+        let limits = Limits::default();
+        assert_eq!(body(&[], b"ignored", &limits).unwrap(), None);
+        assert_eq!(
+            body(&["Content-Length: 0"], b"", &limits).unwrap(),
+            Some(vec![])
+        );
+
+        let mut reader: &[u8] = b"helloGET / HTTP/1.1\r\n";
+        let (read, trailers) =
+            read_body(&mut reader, &fields(&["Content-Length: 5"]), &limits).unwrap();
+        assert_eq!(read.unwrap(), b"hello");
+        assert!(trailers.is_empty());
+        assert_eq!(reader, b"GET / HTTP/1.1\r\n");
+
+        let error = body(&["Content-Length: 10"], b"short", &limits).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn reads_a_chunked_body_over_the_content_length() {
+        let limits = Limits::default();
+        let mut reader: &[u8] = b"5\r\nhello\r\n0\r\nX-Sum: 1\r\n\r\nnext";
+        let headers = fields(&["Content-Length: 3", "Transfer-Encoding: chunked"]);
+        let (read, trailers) = read_body(&mut reader, &headers, &limits).unwrap();
+        assert_eq!(read.unwrap(), b"hello");
+        assert_eq!(trailers.get("X-Sum"), Some("1"));
+        assert_eq!(reader, b"next");
+    }
+
+    #[test]
+    fn refuses_a_body_over_the_limit() {
+        let limits = Limits {
+            max_body_size: 5,
+            ..Limits::default()
+        };
+        assert_eq!(
+            body(&["Content-Length: 5"], b"hello", &limits).unwrap(),
+            Some(b"hello".to_vec())
+        );
+        assert_eq!(
+            body(
+                &["Transfer-Encoding: chunked"],
+                b"5\r\nhello\r\n0\r\n\r\n",
+                &limits
+            )
+            .unwrap(),
+            Some(b"hello".to_vec())
+        );
+
+        // refused from the header alone, before anything is read
+        let error = body(&["Content-Length: 6"], b"", &limits).unwrap_err();
+        assert_eq!(HttpError::status_of(&error), Some(413));
+        let chunked = b"3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n";
+        let error = body(&["Transfer-Encoding: chunked"], chunked, &limits).unwrap_err();
+        assert_eq!(HttpError::status_of(&error), Some(413));
+    }
+}
@@ -0,0 +1,73 @@
+use std::ops::Range;
+
+/// Most ranges served for a single request, a `Range` header asking for
+/// more is ignored and the whole representation is sent instead.
+pub static MAX_RANGES: usize = 16;
+
+/// The part of a representation asked for by a `Range` header.
+///
+/// ```
+/// use rust_server::core::http::ByteRanges;
+///
+/// assert_eq!(ByteRanges::parse("bytes=0-99", 1000), ByteRanges::Partial(vec![0..100]));
+/// assert_eq!(ByteRanges::parse("bytes=-100", 1000), ByteRanges::Partial(vec![900..1000]));
+/// assert_eq!(ByteRanges::parse("bytes=2000-", 1000), ByteRanges::Unsatisfiable);
+/// assert_eq!(ByteRanges::parse("lines=1-2", 1000), ByteRanges::Full);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ByteRanges {
+    /// No usable range was asked for, send everything with `200 OK`.
+    Full,
+    /// The satisfiable ranges in the order asked for, send them with
+    /// `206 Partial Content`.
+    Partial(Vec<Range<u64>>),
+    /// None of the ranges overlap the representation, answer with
+    /// `416 Range Not Satisfiable`.
+    Unsatisfiable,
+}
+
+impl ByteRanges {
+    /// Parses a `Range` header against a representation of `size` bytes.
+    /// Headers which are malformed or use a unit other than `bytes` are
+    /// ignored, as are ranges which don't overlap the representation unless
+    /// none of them do. An empty representation is always sent in full.
+    pub fn parse(header: &str, size: u64) -> Self {
+        let Some(specs) = header.trim().strip_prefix("bytes=") else {
+            return ByteRanges::Full;
+        };
+
+        let mut ranges = Vec::new();
+        for spec in specs
+            .split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+        {
+            let Some((first, last)) = spec.split_once('-') else {
+                return ByteRanges::Full;
+            };
+            let range = match (first.parse::<u64>(), last.parse::<u64>()) {
+                (Ok(first), Ok(last)) if first <= last => first..size.min(last.saturating_add(1)),
+                (Ok(first), Err(_)) if last.is_empty() => first..size,
+                (Err(_), Ok(suffix)) if first.is_empty() => size.saturating_sub(suffix)..size,
+                _ => return ByteRanges::Full,
+            };
+            if !range.is_empty() {
+                ranges.push(range);
+            }
+            if ranges.len() > MAX_RANGES {
+                return ByteRanges::Full;
+            }
+        }
+
+        match ranges.is_empty() {
+            _ if size == 0 || specs.trim().is_empty() => ByteRanges::Full,
+            true => ByteRanges::Unsatisfiable,
+            false => ByteRanges::Partial(ranges),
+        }
+    }
+}
+
+/// The `Content-Range` value for part of a representation, e.g. `bytes 0-99/1000`.
+pub fn content_range(range: &Range<u64>, size: u64) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end - 1, size)
+}
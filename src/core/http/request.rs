@@ -1,35 +1,52 @@
+use crate::core::data::log::info;
 use crate::core::http;
+use crate::core::http::parser::RequestHead;
+use crate::core::http::ChunkedWriter;
+use crate::core::http::Connection;
+use crate::core::http::HeaderMap;
 use crate::core::http::HttpCodec;
+use crate::core::http::HttpResponse;
+use crate::core::http::Limits;
+use crate::core::http::Method;
+use crate::core::http::Version;
+use crate::core::server::pattern::Params;
+use crate::core::server::State;
 use crate::core::util;
+use crate::core::util::{NotFoundPage, PublicDir};
 use std::{
-    collections::HashMap,
     io::{self, ErrorKind, Write},
     net::TcpStream,
+    path::PathBuf,
+    sync::Arc,
 };
 
-/// A fast and lightweight HTTP request parser which will only
-/// read the first line of the request to obtain the method, uri, and protocol.
-/// The headers and body will be read later when the request is processed.
+/// An HTTP/1.x request parsed from a TcpStream, containing the request line,
+/// header fields and the body (if one was framed by `Content-Length` or
+/// chunked transfer-encoding, in which case trailer fields are kept as well).
 #[derive(Debug)]
 pub struct Request {
     pub protocol: String,
     pub method: String,
     pub uri: String,
-    pub headers: Option<HashMap<String, String>>,
+    pub headers: HeaderMap,
     stream: TcpStream,
     body: Option<Vec<u8>>,
+    trailers: HeaderMap,
+    keep_alive: bool,
+    params: Params,
+    allowed_methods: Vec<Method>,
+    state: Arc<State>,
+    deferred: bool,
+    response: Option<HttpResponse>,
 }
 
 impl Request {
-    /// Maximum size of the peek buffer
-    const MAX_PEEK_SIZE: usize = 1024;
-
     pub fn new(
         protocol: String,
         method: String,
         uri: String,
         stream: TcpStream,
-        headers: Option<HashMap<String, String>>,
+        headers: HeaderMap,
         body: Option<Vec<u8>>,
     ) -> Self {
         Request {
@@ -39,93 +56,196 @@ impl Request {
             stream,
             headers,
             body,
+            trailers: HeaderMap::new(),
+            keep_alive: false,
+            params: Params::new(),
+            allowed_methods: Vec::new(),
+            state: Arc::default(),
+            deferred: false,
+            response: None,
         }
     }
 
+    /// Creates a request from a parsed head and body which were read from the stream.
+    /// Keep-alive defaults to what the client asked for in the `Connection` header.
+    pub(crate) fn from_parts(
+        head: RequestHead,
+        stream: TcpStream,
+        body: Option<Vec<u8>>,
+        trailers: HeaderMap,
+    ) -> Self {
+        let mut request = Request::new(
+            head.protocol,
+            head.method,
+            head.uri,
+            stream,
+            head.headers,
+            body,
+        );
+        request.trailers = trailers;
+        request.keep_alive = request.wants_keep_alive();
+        request
+    }
+
     /// Create a new request from a TcpStream.
-    /// NOTE: This will attempt to read the first 1024 bytes from the stream
-    /// to obtain the request line. The headers and body will be read later
-    /// when the request is processed.
+    /// NOTE: This reads the request line, every header field and the body
+    /// from the stream, blocking until the full request has arrived or one of
+    /// the default `Limits` is hit. The connection is not kept alive, use a
+    /// `Connection` to serve several requests.
     pub fn from(stream: TcpStream) -> Result<Self, std::io::Error> {
-        let mut buffer = [0_u8; Self::MAX_PEEK_SIZE];
-        let n = stream.peek(&mut buffer).map_err(|e| {
-            io::Error::new(
-                ErrorKind::ConnectionAborted,
-                format!("Failed to peek stream: {}", e),
-            )
-        })?;
-
-        if n == 0 {
-            return Err(io::Error::new(ErrorKind::InvalidData, "Empty request"));
-        } else {
-            // println!("[request] read {} / {} bytes", n, Self::MAX_PEEK_SIZE);
+        Connection::new(stream, 1, Limits::default())
+            .next_request()?
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Empty request"))
+    }
+
+    /// Sets the version and `Connection` header every response to this request must carry.
+    fn prepare(&self, res: HttpResponse) -> HttpResponse {
+        res.version(self.version())
+            .header("Connection", self.connection_header())
+    }
+
+    /// Encodes the response and sends it over the TcpStream,
+    /// returning the status code which was sent. A `HEAD` request gets
+    /// the head with the body's `Content-Length`, but not the body.
+    ///
+    /// While responses are deferred (during the middleware pipeline) the
+    /// response is held on the request until `flush_response` is called.
+    pub fn send(&mut self, res: HttpResponse) -> http::Response {
+        if self.deferred {
+            let status = res.status_code();
+            self.response = Some(res);
+            return Ok(status);
         }
 
-        // find the first line ending in the buffer (use memchr for performance?)
-        let line_end = buffer[..n]
-            .iter()
-            .position(|&b| b == b'\n')
-            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "No line ending found"))?;
-
-        // attempt to read the first request line from the buffer
-        // and parse the method, uri, and protocol
-        let mut method = None;
-        let mut uri = None;
-        let mut protocol = None;
-        for (i, part) in std::str::from_utf8(&buffer[..line_end])
-            .map_err(|e| {
-                io::Error::new(io::ErrorKind::InvalidData, format!("Invalid UTF-8: {}", e))
-            })?
-            .trim()
-            .split_whitespace()
-            .enumerate()
-        {
-            match i {
-                0 => method = Some(part.to_string()),
-                1 => uri = Some(part.to_string()),
-                2 => protocol = Some(part.to_string()),
-                _ => break,
+        let res = self.prepare(res);
+        match self.method == "HEAD" {
+            true => res.encode_without_body_to(&mut self.stream)?,
+            false => res.encode_to(&mut self.stream)?,
+        };
+        self.stream.flush()?;
+        Ok(res.status_code())
+    }
+
+    /// Holds responses passed to `send` instead of writing them, so
+    /// middleware can modify them after the handler returns.
+    pub fn defer_response(&mut self, deferred: bool) {
+        self.deferred = deferred;
+    }
+
+    /// Writes the response held by `send`, if any, returning its status.
+    pub fn flush_response(&mut self) -> io::Result<Option<u16>> {
+        match self.response.take() {
+            Some(res) => {
+                self.deferred = false;
+                self.send(res).map(Some)
             }
+            None => Ok(None),
         }
+    }
 
-        match (method, uri, protocol) {
-            (Some(method), Some(uri), Some(protocol)) => Ok(Request {
-                method,
-                uri,
-                protocol,
-                stream,
-                headers: None,
-                body: None,
-            }),
-            _ => Err(io::Error::new(
-                ErrorKind::InvalidData,
-                "Invalid request line: missing required parts",
-            )),
+    /// The response sent by the handler which has not been written yet.
+    #[inline]
+    pub fn response(&self) -> Option<&HttpResponse> {
+        self.response.as_ref()
+    }
+
+    #[inline]
+    pub fn response_mut(&mut self) -> Option<&mut HttpResponse> {
+        self.response.as_mut()
+    }
+
+    /// Sends only the status line and headers of the response, the caller then
+    /// writes the body to `stream()`. The headers must include `Content-Length`
+    /// or the connection is closed after the body to mark where it ends.
+    pub fn send_head(&mut self, res: HttpResponse) -> http::Response {
+        if !res.headers().contains("Content-Length") {
+            self.keep_alive = false;
         }
+        let res = self.prepare(res);
+        res.encode_head_to(&mut self.stream)?;
+        Ok(res.status_code())
     }
 
-    /// Sends a request as raw bytes over the TcpStream.
-    pub fn send(&mut self, res: impl HttpCodec) -> http::Response {
-        res.encode_to(&mut self.stream)?;
-        self.close()?;
-        Ok(200)
+    /// Sends the head of the response with `Transfer-Encoding: chunked` and returns
+    /// a writer which streams everything written into it as chunks. The caller must
+    /// call `finish` on the writer once the body is complete.
+    ///
+    /// HTTP/1.0 clients get a close-delimited body instead of chunks.
+    pub fn send_chunked(&mut self, res: HttpResponse) -> io::Result<ChunkedWriter<&TcpStream>> {
+        if self.version().supports_chunked() {
+            let res = self.prepare(res.header("Transfer-Encoding", "chunked"));
+            res.encode_head_to(&mut self.stream)?;
+            Ok(ChunkedWriter::new(&self.stream))
+        } else {
+            self.keep_alive = false;
+            let res = self.prepare(res);
+            res.encode_head_to(&mut self.stream)?;
+            Ok(ChunkedWriter::identity(&self.stream))
+        }
     }
 
-    /// Calls `find_static_file` and `copy_static_file` to send a static file over the TcpStream.
-    /// Then attempts to close the connection and return the result.
+    /// Sends a static file from the server's public directory over the
+    /// TcpStream, or the not found page with `404 Not Found` if it doesn't exist.
     pub fn send_static(&mut self, uri: &str) -> http::Response {
-        let static_file = util::find_static_file(uri);
-        let result = util::copy_static_file(self, static_file)?;
-        self.close()?;
+        let result = match util::static_file_in(&self.public_dir(), uri) {
+            Some(file) => util::copy_static_file(self, file.display().to_string())?,
+            None => {
+                let page = self.not_found_page();
+                match page.is_file() {
+                    true => util::send_file(
+                        self,
+                        &page.display().to_string(),
+                        HttpResponse::not_found(),
+                    )?,
+                    false => self.send(HttpResponse::not_found().text("Not Found"))?,
+                }
+            }
+        };
+        self.stream.flush()?;
         Ok(result)
     }
 
-    /// Flushes the TcpStream and shuts down the connection.
+    /// Flushes the TcpStream and shuts down the connection, even if
+    /// it would otherwise have been kept alive for another request.
     pub fn close(&mut self) -> Result<(), std::io::Error> {
-        println!("[request] closing {}", self.uri());
+        info!("[request] closing {}", self.uri());
+        self.keep_alive = false;
         self.stream.flush()?;
-        self.stream.shutdown(std::net::Shutdown::Both)?;
-        Ok(())
+        match self.stream.shutdown(std::net::Shutdown::Both) {
+            Err(e) if e.kind() != ErrorKind::NotConnected => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns true if the client asked for the connection to persist,
+    /// HTTP/1.1 connections persist unless `Connection: close` is sent
+    /// while HTTP/1.0 connections must opt in with `Connection: keep-alive`.
+    pub fn wants_keep_alive(&self) -> bool {
+        match self.protocol.as_str() {
+            "HTTP/1.0" => self.headers.has_token("Connection", "keep-alive"),
+            _ => !self.headers.has_token("Connection", "close"),
+        }
+    }
+
+    /// Returns true if the connection will be reused after this response.
+    #[inline]
+    pub fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+
+    /// Overrides whether the connection is reused after this response,
+    /// must be called before the response is written.
+    #[inline]
+    pub fn set_keep_alive(&mut self, keep_alive: bool) {
+        self.keep_alive = keep_alive;
+    }
+
+    /// The value of the `Connection` header to send with the response.
+    pub fn connection_header(&self) -> &'static str {
+        match self.keep_alive {
+            true => "keep-alive",
+            false => "close",
+        }
     }
 
     pub fn stream(&self) -> &TcpStream {
@@ -142,9 +262,93 @@ impl Request {
         &self.uri
     }
 
+    /// The path of the request uri without the query string.
     #[inline]
-    pub fn headers(&self) -> Option<&HashMap<String, String>> {
-        self.headers.as_ref()
+    pub fn path(&self) -> &str {
+        match self.uri.split_once('?') {
+            Some((path, _)) => path,
+            None => &self.uri,
+        }
+    }
+
+    /// The query string of the request uri (without the `?`), if any.
+    #[inline]
+    pub fn query(&self) -> Option<&str> {
+        self.uri.split_once('?').map(|(_, query)| query)
+    }
+
+    /// Returns the value captured by a named route parameter,
+    /// e.g. `id` for `/users/:id` or `rest` for `/files/*rest`.
+    #[inline]
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[inline]
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// Replaces the captured route parameters, called by the router.
+    pub fn set_params(&mut self, params: Params) {
+        self.params = params;
+    }
+
+    /// The methods which have a route for this path, set by the router
+    /// when it answers with the method not allowed handler.
+    #[inline]
+    pub fn allowed_methods(&self) -> &[Method] {
+        &self.allowed_methods
+    }
+
+    pub fn set_allowed_methods(&mut self, methods: Vec<Method>) {
+        self.allowed_methods = methods;
+    }
+
+    /// Returns the application state of the given type which was added
+    /// with `Server::manage`.
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.state.get::<T>()
+    }
+
+    /// The directory static files are served from, as configured on the server.
+    pub fn public_dir(&self) -> PathBuf {
+        match self.state::<PublicDir>() {
+            Some(dir) => dir.0.clone(),
+            None => PathBuf::from(util::PUBLIC_DIR),
+        }
+    }
+
+    /// The page sent when a static file doesn't exist.
+    pub fn not_found_page(&self) -> PathBuf {
+        match self.state::<NotFoundPage>() {
+            Some(page) => page.0.clone(),
+            None => self.public_dir().join("404.html"),
+        }
+    }
+
+    /// Finds the file for a uri in the public directory, or the not found page.
+    pub fn find_static_file(&self, uri: &str) -> String {
+        util::find_static_file_in(&self.public_dir(), &self.not_found_page(), uri)
+    }
+
+    /// Shares the server's application state with the request, called by the server.
+    pub fn set_state(&mut self, state: Arc<State>) {
+        self.state = state;
+    }
+
+    #[inline]
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the first value of a header field, ignoring case.
+    #[inline]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
     }
 
     #[inline]
@@ -152,8 +356,20 @@ impl Request {
         self.body.as_ref()
     }
 
+    /// Trailer fields received after a chunked body.
+    #[inline]
+    pub fn trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+
     #[inline]
     pub fn protocol(&self) -> &str {
         &self.protocol
     }
+
+    /// The protocol version responses to this request are sent with.
+    #[inline]
+    pub fn version(&self) -> Version {
+        Version::from(self.protocol.as_str())
+    }
 }
@@ -0,0 +1,34 @@
+/// HTTP Version
+///
+/// The protocol version used on the status line of a response, echoing
+/// the version of the request. Anything newer than HTTP/1.1 sent over a
+/// plain text connection is answered as HTTP/1.1.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Version {
+    Http10,
+    #[default]
+    Http11,
+}
+
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
+        }
+    }
+
+    /// HTTP/1.0 has neither persistent connections by default nor chunked encoding.
+    pub fn supports_chunked(&self) -> bool {
+        *self == Version::Http11
+    }
+}
+
+impl From<&str> for Version {
+    fn from(protocol: &str) -> Self {
+        match protocol {
+            "HTTP/1.0" | "HTTP/0.9" => Version::Http10,
+            _ => Version::Http11,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::core::data::mime;
+use crate::core::http::status;
+use crate::core::http::{ContentEncoding, HttpResponse, Request};
+use crate::core::middleware::middleware::MiddlewareResult;
+use crate::core::middleware::{Middleware, Next};
+
+/// Compression
+///
+/// Compresses response bodies with the coding the client prefers in its
+/// `Accept-Encoding`. Added with `Server::use_middleware` it compresses
+/// responses passed to `Request::send`, and set on `StaticFiles` it
+/// compresses files and serves precompressed siblings.
+///
+/// Only compressible types (see `mime::is_compressible`) of at least
+/// `min_size` bytes are compressed, and they're sent with
+/// `Vary: Accept-Encoding` whichever coding is picked.
+///
+/// ```no_run
+/// use rust_server::core::middleware::Compression;
+/// use rust_server::core::server::{Server, StaticFiles};
+///
+/// let mut server = Server::new("localhost:8080").unwrap();
+/// server.use_middleware(Compression::default());
+/// server.configure(|route| {
+///     route.mount("/", StaticFiles::new("./dist").compression(Compression::default()));
+/// });
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Compression {
+    /// Codings to compress with, in order of preference when the client
+    /// likes several equally. Only `Gzip` and `Deflate` can be encoded.
+    pub encodings: Vec<ContentEncoding>,
+    /// Smaller bodies are sent as is, they'd barely shrink.
+    pub min_size: usize,
+    /// Larger static files are sent as is, as files are compressed in memory.
+    /// Compressed files are kept in memory until they change, see
+    /// `util::send_file_encoded`.
+    pub max_file_size: u64,
+    /// Serve `app.js.br` or `app.js.gz` in place of `app.js` when it exists
+    /// and the client accepts the coding, so files can be compressed at
+    /// build time, including with brotli.
+    pub precompressed: bool,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            encodings: vec![ContentEncoding::Gzip, ContentEncoding::Deflate],
+            min_size: 1024,
+            max_file_size: 8 * 1024 * 1024,
+            precompressed: true,
+        }
+    }
+}
+
+impl Compression {
+    /// Whether a body of this type and size is worth compressing.
+    pub fn applies_to(&self, content_type: &str, size: u64) -> bool {
+        mime::is_compressible(content_type) && size >= self.min_size as u64
+    }
+
+    /// Compresses a response in place with the best coding the client
+    /// accepts, marking its entity tag as belonging to the compressed body.
+    /// Streamed, partial and already encoded responses are left alone.
+    pub fn compress(&self, response: &mut HttpResponse, accept_encoding: &str) {
+        let headers = response.headers();
+        let content_type = headers.get("Content-Type").unwrap_or_default();
+        if status::is_bodyless(response.status_code())
+            || response.status_code() == 206
+            || headers.contains("Content-Encoding")
+            || headers.has_token("Cache-Control", "no-transform")
+            || !self.applies_to(content_type, response.body_bytes().len() as u64)
+        {
+            return;
+        }
+
+        if !response.headers().has_token("Vary", "Accept-Encoding") {
+            response.headers_mut().append("Vary", "Accept-Encoding");
+        }
+
+        let encoding = ContentEncoding::negotiate(accept_encoding, &self.encodings);
+        let Some(body) = encoding.encode(response.body_bytes()) else {
+            return;
+        };
+        *response.body_mut() = body;
+
+        // a length set by the handler is the uncompressed one, it's filled
+        // in again for the new body when the response is encoded
+        let headers = response.headers_mut();
+        headers.remove("Content-Length");
+        headers.insert("Content-Encoding", encoding.as_str());
+        if let Some(etag) = headers.get("ETag").map(|etag| variant_tag(etag, encoding)) {
+            headers.insert("ETag", etag);
+        }
+    }
+}
+
+impl Middleware for Compression {
+    fn handle(&self, request: &mut Request, next: Next) -> MiddlewareResult {
+        let accept_encoding = request
+            .headers()
+            .get("Accept-Encoding")
+            .unwrap_or_default()
+            .to_string();
+        let status = next.run(request)?;
+
+        if let Some(response) = request.response_mut() {
+            self.compress(response, &accept_encoding);
+        }
+        Ok(status)
+    }
+}
+
+/// The entity tag of an encoded variant, e.g. `"abc-gzip"` for `"abc"`, so
+/// caches never mix up the compressed and uncompressed bodies.
+pub fn variant_tag(etag: &str, encoding: ContentEncoding) -> String {
+    match etag.strip_suffix('"') {
+        Some(tag) => format!("{}-{}\"", tag, encoding.as_str()),
+        None => etag.to_string(),
+    }
+}
@@ -1,15 +1,81 @@
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::thread::JoinHandle;
+use crate::core::http::Request;
+use crate::core::server::{RouteActions, RouteHandler};
+use std::sync::Arc;
 
-pub type MiddlewareResult = Result<u16, u8>;
+pub type MiddlewareResult = RouteActions;
 
+/// Middleware
+///
+/// Wraps the handling of a request. Middleware runs in the order it was
+/// registered and each one decides whether to continue by calling
+/// `next.run(request)`, so it can:
+///
+/// - inspect or modify the `Request` before the route handler runs,
+/// - short-circuit by sending a response without calling `next`,
+/// - post-process the response through `Request::response_mut` once
+///   `next` returns, before it is written to the client.
+///
+/// Responses streamed with `send_head`, `send_chunked` or `send_static`
+/// are written immediately and can't be post-processed.
+///
+/// Closures with the signature of `handle` are middleware as well.
+///
+/// ```
+/// use rust_server::core::http::HttpResponse;
+/// use rust_server::core::middleware::Next;
+/// use rust_server::core::Request;
+///
+/// fn require_token(request: &mut Request, next: Next) -> std::io::Result<u16> {
+///     match request.header("Authorization") {
+///         Some("Bearer secret") => next.run(request),
+///         _ => request.send(HttpResponse::new(401).text("Unauthorized")),
+///     }
+/// }
+/// ```
 pub trait Middleware: Send + Sync + 'static {
-    fn handle(&self, context: Arc<Mutex<Context>>) -> MiddlewareResult;
+    fn handle(&self, request: &mut Request, next: Next) -> MiddlewareResult;
 }
 
-pub struct Context<T> {
-    pub request: Request,
-    pub response: Option<Response>,
-    pub handled: bool,
+impl<F> Middleware for F
+where
+    F: Fn(&mut Request, Next) -> MiddlewareResult + Send + Sync + 'static,
+{
+    fn handle(&self, request: &mut Request, next: Next) -> MiddlewareResult {
+        self(request, next)
+    }
+}
+
+pub type MiddlewareStack = Vec<Arc<dyn Middleware>>;
+
+/// The rest of the pipeline after the current middleware, ending with the
+/// route handler.
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn Middleware>],
+    handler: &'a RouteHandler,
+}
+
+impl<'a> Next<'a> {
+    pub fn new(middleware: &'a [Arc<dyn Middleware>], handler: &'a RouteHandler) -> Self {
+        Next {
+            middleware,
+            handler,
+        }
+    }
+
+    /// Runs the next middleware, or the route handler if none are left.
+    pub fn run(self, request: &mut Request) -> MiddlewareResult {
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => middleware.handle(request, Next::new(rest, self.handler)),
+            None => (self.handler)(request),
+        }
+    }
+}
+
+/// Wraps a handler so that the middleware runs around it every time it is
+/// called, used to scope middleware to individual routes.
+pub fn wrap(middleware: MiddlewareStack, handler: RouteHandler) -> RouteHandler {
+    if middleware.is_empty() {
+        return handler;
+    }
+    Arc::new(move |request: &mut Request| Next::new(&middleware, &handler).run(request))
 }
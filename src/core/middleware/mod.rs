@@ -0,0 +1,8 @@
+pub mod compression;
+#[allow(clippy::module_inception)]
+pub mod middleware;
+
+pub use compression::Compression;
+pub use middleware::Middleware;
+pub use middleware::MiddlewareStack;
+pub use middleware::Next;
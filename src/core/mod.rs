@@ -1,5 +1,6 @@
 pub mod data;
 pub mod http;
+pub mod middleware;
 pub mod server;
 pub mod traits;
 
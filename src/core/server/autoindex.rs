@@ -0,0 +1,343 @@
+use crate::core::http::date::format_http_date;
+use crate::core::util;
+use std::cmp::Ordering;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An entry of a directory listing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    /// Size in bytes, zero for directories.
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// The column a listing is sorted by, directories always come first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+        }
+    }
+}
+
+/// How a listing is sorted, read from the `sort` and `order` query
+/// parameters, e.g. `?sort=size&order=desc`. Unknown values are ignored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Sort {
+    pub fn from_query(query: Option<&str>) -> Self {
+        let mut sort = Sort::default();
+        let pairs = query.unwrap_or_default().split('&');
+        for (key, value) in pairs.filter_map(|pair| pair.split_once('=')) {
+            match (key, value) {
+                ("sort", "name") => sort.key = SortKey::Name,
+                ("sort", "size") => sort.key = SortKey::Size,
+                ("sort", "modified") => sort.key = SortKey::Modified,
+                ("order", "asc") => sort.descending = false,
+                ("order", "desc") => sort.descending = true,
+                _ => {}
+            }
+        }
+        sort
+    }
+
+    fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+        let ordering = match self.key {
+            SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        }
+        .then_with(|| a.name.cmp(&b.name));
+        match self.descending {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    }
+}
+
+/// Reads and sorts the entries of a directory, leaving out hidden ones
+/// unless `hidden` is set and entries whose name isn't valid UTF-8.
+pub fn read_entries(dir: &Path, hidden: bool, sort: Sort) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if name.starts_with('.') && !hidden {
+            continue;
+        }
+        // follows symlinks, links which don't resolve are left out
+        let Ok(metadata) = fs::metadata(entry.path()) else {
+            continue;
+        };
+        entries.push(Entry {
+            name,
+            is_dir: metadata.is_dir(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: metadata.modified().ok(),
+        });
+    }
+
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| sort.compare(a, b)));
+    Ok(entries)
+}
+
+/// Renders a listing as an HTML page for the directory at the request path
+/// `path`, which must end with `/`. Names are escaped, and the column
+/// headers link to the listing sorted by that column.
+pub fn render_html(path: &str, entries: &[Entry], sort: Sort, parent: bool) -> String {
+    let title = escape_html(&util::percent_decode(path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Index of {title}</title>\n\
+         <style>body{{font-family:sans-serif}}td,th{{padding:2px 12px;text-align:left}}\
+         td:nth-child(2){{text-align:right}}</style>\n\
+         </head>\n<body>\n<h1>Index of {title}</h1>\n<table>\n<tr>"
+    );
+
+    for key in [SortKey::Name, SortKey::Size, SortKey::Modified] {
+        // clicking the current column again reverses it
+        let order = match sort.key == key && !sort.descending {
+            true => "desc",
+            false => "asc",
+        };
+        html.push_str(&format!(
+            "<th><a href=\"?sort={}&amp;order={}\">{}</a></th>",
+            key.as_str(),
+            order,
+            key.as_str()
+        ));
+    }
+    html.push_str("</tr>\n");
+
+    if parent {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        let size = match entry.is_dir {
+            true => "-".to_string(),
+            false => entry.size.to_string(),
+        };
+        let modified = entry.modified.map(format_http_date).unwrap_or_default();
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}{}{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(path),
+            escape_html(&util::percent_encode(&entry.name)),
+            suffix,
+            escape_html(&entry.name),
+            suffix,
+            size,
+            modified
+        ));
+    }
+
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// Renders a listing as a JSON array of objects with the `name`, `type`
+/// (`file` or `directory`), `size` in bytes and `modified` time in seconds
+/// since the epoch of each entry.
+pub fn render_json(entries: &[Entry]) -> String {
+    let items: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            let modified = entry
+                .modified
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or("null".to_string(), |time| time.as_secs().to_string());
+            format!(
+                "{{\"name\":\"{}\",\"type\":\"{}\",\"size\":{},\"modified\":{}}}",
+                escape_json(&entry.name),
+                if entry.is_dir { "directory" } else { "file" },
+                entry.size,
+                modified
+            )
+        })
+        .collect();
+    format!("[{}]", items.join(","))
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn file(name: &str, size: u64) -> Entry {
+        Entry {
+            name: name.to_string(),
+            is_dir: false,
+            size,
+            modified: Some(UNIX_EPOCH + Duration::from_secs(784111777)),
+        }
+    }
+
+    fn names(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    /// A fresh directory under the system temp dir holding the files
+    /// `b.txt` (3 bytes), `A.txt` (1 byte), `c.txt` (2 bytes) and
+    /// `.hidden`, and the directories `z` and `.git`.
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rust-server-autoindex-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("z")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join("b.txt"), "bbb").unwrap();
+        fs::write(dir.join("A.txt"), "a").unwrap();
+        fs::write(dir.join("c.txt"), "cc").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+        dir
+    }
+
+    #[test]
+    fn escapes_names_in_html() {
+        let entries = [file("<script>alert(1)</script>", 1), file("a\"b&c'd", 2)];
+        let html = render_html("/<dir>/", &entries, Sort::default(), true);
+
+        assert!(!html.contains("<script>"), "{}", html);
+        assert!(!html.contains("a\"b"), "{}", html);
+        assert!(
+            html.contains("<title>Index of /&lt;dir&gt;/</title>"),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains(">&lt;script&gt;alert(1)&lt;/script&gt;</a>"),
+            "{}",
+            html
+        );
+        assert!(html.contains(">a&quot;b&amp;c&#39;d</a>"), "{}", html);
+        // the link is percent-encoded, leaving nothing to escape but the path
+        assert!(
+            html.contains("href=\"/&lt;dir&gt;/a%22b%26c%27d\""),
+            "{}",
+            html
+        );
+        assert!(html.contains("<a href=\"../\">../</a>"), "{}", html);
+    }
+
+    #[test]
+    fn escapes_names_in_json() {
+        let entries = [
+            file("<script>", 1),
+            file("quote\" back\\slash", 2),
+            file("line\nbreak\u{1}", 3),
+        ];
+        assert_eq!(
+            render_json(&entries),
+            "[{\"name\":\"<script>\",\"type\":\"file\",\"size\":1,\"modified\":784111777},\
+             {\"name\":\"quote\\\" back\\\\slash\",\"type\":\"file\",\"size\":2,\"modified\":784111777},\
+             {\"name\":\"line\\nbreak\\u0001\",\"type\":\"file\",\"size\":3,\"modified\":784111777}]"
+        );
+        assert_eq!(render_json(&[]), "[]");
+    }
+
+    #[test]
+    fn lists_directories_first_in_sorted_order() {
+        let dir = dir("sort");
+        let read = |query| read_entries(&dir, false, Sort::from_query(Some(query))).unwrap();
+
+        let entries = read("");
+        assert_eq!(names(&entries), ["z", "A.txt", "b.txt", "c.txt"]);
+        assert!(entries[0].is_dir);
+        assert_eq!(entries[0].size, 0);
+        assert_eq!(entries[2].size, 3);
+
+        assert_eq!(names(&read("order=desc")), ["z", "c.txt", "b.txt", "A.txt"]);
+        assert_eq!(names(&read("sort=size")), ["z", "A.txt", "c.txt", "b.txt"]);
+        assert_eq!(
+            names(&read("sort=size&order=desc")),
+            ["z", "b.txt", "c.txt", "A.txt"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn leaves_out_hidden_entries_unless_asked() {
+        let dir = dir("hidden");
+        let entries = read_entries(&dir, false, Sort::default()).unwrap();
+        assert!(!entries.iter().any(|entry| entry.name.starts_with('.')));
+
+        let entries = read_entries(&dir, true, Sort::default()).unwrap();
+        assert_eq!(
+            names(&entries),
+            [".git", "z", ".hidden", "A.txt", "b.txt", "c.txt"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_the_sort_from_the_query() {
+        assert_eq!(Sort::from_query(None), Sort::default());
+        assert_eq!(
+            Sort::from_query(Some("order=desc&sort=modified")),
+            Sort {
+                key: SortKey::Modified,
+                descending: true
+            }
+        );
+        assert_eq!(
+            Sort::from_query(Some("sort=colour&order=up&sort")),
+            Sort::default()
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use crate::core::data::log::LogLevel;
+use crate::core::http::Limits;
+use crate::core::server::config::ServerConfig;
+use crate::core::server::Server;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Server Builder
+///
+/// Configures a `Server` in code. Nothing is bound until `build`, which
+/// returns an error instead of panicking if the config is invalid or an
+/// address can't be bound.
+///
+/// The server only speaks plain HTTP, there is no TLS option. To serve
+/// HTTPS, put it behind a reverse proxy (nginx, Caddy, a load balancer)
+/// which terminates TLS and forwards requests to one of the bound addresses.
+///
+/// ```no_run
+/// use rust_server::core::server::Server;
+/// use std::time::Duration;
+///
+/// let mut server = Server::builder()
+///     .bind("0.0.0.0:8080")
+///     .bind("[::]:8080")
+///     .threads(8)
+///     .keep_alive_timeout(Duration::from_secs(10))
+///     .public_dir("./dist")
+///     .handle_signals(true)
+///     .build()?;
+///
+/// server.start();
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ServerBuilder {
+    config: ServerConfig,
+    addrs: Vec<String>,
+}
+
+impl ServerBuilder {
+    pub fn new() -> Self {
+        ServerBuilder::default()
+    }
+
+    /// Starts from an existing config, e.g. one loaded from a file.
+    pub fn from_config(config: ServerConfig) -> Self {
+        ServerBuilder {
+            config,
+            addrs: Vec::new(),
+        }
+    }
+
+    /// Adds an address to listen on, may be called several times. Replaces
+    /// the addresses of the config, which default to `localhost:8080`.
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.addrs.push(addr.into());
+        self
+    }
+
+    /// Number of worker threads, defaults to the available parallelism.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = threads;
+        self
+    }
+
+    /// Connections allowed to wait for a worker before new ones are
+    /// answered with `503 Service Unavailable`.
+    pub fn max_queued(mut self, max_queued: usize) -> Self {
+        self.config.max_queued = max_queued;
+        self
+    }
+
+    pub fn max_requests_per_connection(mut self, max: usize) -> Self {
+        self.config.max_requests_per_connection = max;
+        self
+    }
+
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.config.keep_alive_timeout = timeout;
+        self
+    }
+
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Timeouts and size limits for reading requests and writing responses.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    pub fn header_timeout(mut self, timeout: Duration) -> Self {
+        self.config.limits.header_timeout = timeout;
+        self
+    }
+
+    pub fn body_timeout(mut self, timeout: Duration) -> Self {
+        self.config.limits.body_timeout = timeout;
+        self
+    }
+
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.config.limits.write_timeout = timeout;
+        self
+    }
+
+    pub fn max_body_size(mut self, max: u64) -> Self {
+        self.config.limits.max_body_size = max;
+        self
+    }
+
+    /// Directory static files are served from.
+    pub fn public_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.public_dir = dir.into();
+        self
+    }
+
+    /// Page sent when a static file doesn't exist.
+    pub fn not_found_page(mut self, page: impl Into<PathBuf>) -> Self {
+        self.config.not_found_page = Some(page.into());
+        self
+    }
+
+    pub fn log_level(mut self, level: LogLevel) -> Self {
+        self.config.log_level = level;
+        self
+    }
+
+    /// Shut down gracefully on SIGINT and SIGTERM instead of letting the
+    /// signal kill the process mid-response.
+    pub fn handle_signals(mut self, handle: bool) -> Self {
+        self.config.handle_signals = handle;
+        self
+    }
+
+    /// The config as it would be built.
+    pub fn config(&self) -> ServerConfig {
+        let mut config = self.config.clone();
+        if !self.addrs.is_empty() {
+            config.addrs = self.addrs.clone();
+        }
+        config
+    }
+
+    /// Validates the config and binds every address.
+    pub fn build(self) -> io::Result<Server> {
+        Server::from_config(self.config())
+    }
+}
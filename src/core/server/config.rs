@@ -0,0 +1,206 @@
+use crate::core::data::log::LogLevel;
+use crate::core::http::Limits;
+use crate::core::util;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// The longest any timeout may be set to.
+pub static MAX_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Server Config
+///
+/// Everything needed to start a `Server`, usually put together with
+/// `Server::builder()`. The defaults listen on `localhost:8080` with one
+/// worker per core and serve static files from `src/public`.
+///
+/// TLS is out of scope, see `ServerBuilder` for serving HTTPS through a
+/// reverse proxy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Addresses to listen on, e.g. `0.0.0.0:80` or `[::1]:8080`.
+    pub addrs: Vec<String>,
+    /// Number of worker threads.
+    pub threads: usize,
+    /// Connections allowed to wait for a worker before new ones are
+    /// answered with `503 Service Unavailable`.
+    pub max_queued: usize,
+    /// Requests served on a connection before it is closed.
+    pub max_requests_per_connection: usize,
+    /// How long an idle keep-alive connection is kept open between requests.
+    pub keep_alive_timeout: Duration,
+    /// How long in-flight requests are given to finish when shutting down.
+    pub shutdown_timeout: Duration,
+    /// Timeouts and size limits for reading requests and writing responses.
+    pub limits: Limits,
+    /// Directory static files are served from.
+    pub public_dir: PathBuf,
+    /// Page sent when a static file doesn't exist, defaults to `404.html`
+    /// in the public directory.
+    pub not_found_page: Option<PathBuf>,
+    pub log_level: LogLevel,
+    /// Shut down gracefully on SIGINT and SIGTERM.
+    pub handle_signals: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            addrs: vec!["localhost:8080".to_string()],
+            threads: default_threads(),
+            max_queued: 1024,
+            max_requests_per_connection: 100,
+            keep_alive_timeout: Duration::from_secs(5),
+            shutdown_timeout: Duration::from_secs(30),
+            limits: Limits::default(),
+            public_dir: PathBuf::from(util::PUBLIC_DIR),
+            not_found_page: None,
+            log_level: LogLevel::default(),
+            handle_signals: false,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Checks the config can be used to start a server.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |message: &str| Err(io::Error::new(ErrorKind::InvalidInput, message));
+
+        if self.addrs.is_empty() {
+            return invalid("at least one bind address is required");
+        }
+        if self.threads == 0 {
+            return invalid("at least one worker thread is required");
+        }
+        if self.max_requests_per_connection == 0 {
+            return invalid("max_requests_per_connection must be at least 1");
+        }
+        if self.max_queued == 0 {
+            return invalid("max_queued must be at least 1");
+        }
+
+        let limits = &self.limits;
+        for (name, value) in [
+            ("max_request_line", limits.max_request_line),
+            ("max_header_count", limits.max_header_count),
+            ("max_header_size", limits.max_header_size),
+        ] {
+            if value == 0 {
+                return invalid(&format!("{} must be at least 1", name));
+            }
+        }
+
+        // a read or write can't wait for no time at all
+        for (name, timeout) in [
+            ("header_timeout", limits.header_timeout),
+            ("body_timeout", limits.body_timeout),
+            ("write_timeout", limits.write_timeout),
+        ] {
+            if timeout.is_zero() {
+                return invalid(&format!("{} must be longer than zero", name));
+            }
+        }
+        for (name, timeout) in [
+            ("header_timeout", limits.header_timeout),
+            ("body_timeout", limits.body_timeout),
+            ("write_timeout", limits.write_timeout),
+            ("keep_alive_timeout", self.keep_alive_timeout),
+            ("shutdown_timeout", self.shutdown_timeout),
+        ] {
+            if timeout > MAX_TIMEOUT {
+                return invalid(&format!("{} must be at most a day", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The default number of worker threads, one per available core.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(4, |threads| threads.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(config: ServerConfig) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn accepts_the_defaults() {
+        assert!(ServerConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn refuses_zero_sizes_and_timeouts() {
+        let config = ServerConfig::default;
+        let limits = Limits::default;
+        assert_eq!(
+            error(ServerConfig {
+                max_queued: 0,
+                ..config()
+            }),
+            "max_queued must be at least 1"
+        );
+        assert_eq!(
+            error(ServerConfig {
+                limits: Limits {
+                    max_header_count: 0,
+                    ..limits()
+                },
+                ..config()
+            }),
+            "max_header_count must be at least 1"
+        );
+        assert_eq!(
+            error(ServerConfig {
+                limits: Limits {
+                    write_timeout: Duration::ZERO,
+                    ..limits()
+                },
+                ..config()
+            }),
+            "write_timeout must be longer than zero"
+        );
+        assert_eq!(
+            error(ServerConfig {
+                limits: Limits {
+                    header_timeout: Duration::ZERO,
+                    ..limits()
+                },
+                ..config()
+            }),
+            "header_timeout must be longer than zero"
+        );
+    }
+
+    #[test]
+    fn refuses_timeouts_over_a_day() {
+        assert_eq!(
+            error(ServerConfig {
+                keep_alive_timeout: Duration::MAX,
+                ..ServerConfig::default()
+            }),
+            "keep_alive_timeout must be at most a day"
+        );
+        assert_eq!(
+            error(ServerConfig {
+                limits: Limits {
+                    body_timeout: MAX_TIMEOUT + Duration::from_secs(1),
+                    ..Limits::default()
+                },
+                ..ServerConfig::default()
+            }),
+            "body_timeout must be at most a day"
+        );
+        assert!(ServerConfig {
+            shutdown_timeout: MAX_TIMEOUT,
+            ..ServerConfig::default()
+        }
+        .validate()
+        .is_ok());
+    }
+}
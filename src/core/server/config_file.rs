@@ -0,0 +1,687 @@
+use crate::core::server::config::ServerConfig;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The file loaded by `ServerConfig::load` when `RUST_SERVER_CONFIG` is unset or empty.
+pub static DEFAULT_CONFIG_FILE: &str = "rust-server.toml";
+
+/// Prefix of the environment variables which override the config file.
+pub static ENV_PREFIX: &str = "RUST_SERVER_";
+
+/// Every setting as `(file key, environment variable without the prefix)`.
+pub static SETTINGS: [(&str, &str); 18] = [
+    ("server.host", "HOST"),
+    ("server.port", "PORT"),
+    ("server.workers", "WORKERS"),
+    ("server.max_queued", "MAX_QUEUED"),
+    (
+        "server.max_requests_per_connection",
+        "MAX_REQUESTS_PER_CONNECTION",
+    ),
+    ("server.handle_signals", "HANDLE_SIGNALS"),
+    ("static.public_dir", "PUBLIC_DIR"),
+    ("static.not_found_page", "NOT_FOUND_PAGE"),
+    ("log.level", "LOG_LEVEL"),
+    ("timeouts.header", "HEADER_TIMEOUT"),
+    ("timeouts.body", "BODY_TIMEOUT"),
+    ("timeouts.write", "WRITE_TIMEOUT"),
+    ("timeouts.keep_alive", "KEEP_ALIVE_TIMEOUT"),
+    ("timeouts.shutdown", "SHUTDOWN_TIMEOUT"),
+    ("limits.max_request_line", "MAX_REQUEST_LINE"),
+    ("limits.max_header_count", "MAX_HEADER_COUNT"),
+    ("limits.max_header_size", "MAX_HEADER_SIZE"),
+    ("limits.max_body_size", "MAX_BODY_SIZE"),
+];
+
+/// A value in the config file, or the text of an environment variable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl Value {
+    fn describe(&self) -> String {
+        match self {
+            Value::String(value) => format!("{:?}", value),
+            Value::Integer(value) => value.to_string(),
+            Value::Boolean(value) => value.to_string(),
+        }
+    }
+
+    fn as_string(&self) -> Result<String, String> {
+        match self {
+            Value::String(value) => Ok(value.clone()),
+            other => Err(format!("expected a string, found {}", other.describe())),
+        }
+    }
+
+    fn as_u64(&self) -> Result<u64, String> {
+        let number = match self {
+            Value::Integer(value) => u64::try_from(*value).ok(),
+            Value::String(value) => value.trim().replace('_', "").parse().ok(),
+            Value::Boolean(_) => None,
+        };
+        number.ok_or_else(|| format!("expected a positive integer, found {}", self.describe()))
+    }
+
+    fn as_usize(&self) -> Result<usize, String> {
+        let number = self.as_u64()?;
+        usize::try_from(number).map_err(|_| format!("{} is too large", number))
+    }
+
+    fn as_bool(&self) -> Result<bool, String> {
+        match self {
+            Value::Boolean(value) => Ok(*value),
+            Value::String(value) => match value.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok(true),
+                "false" | "no" | "off" | "0" => Ok(false),
+                _ => Err(format!("expected true or false, found {:?}", value)),
+            },
+            other => Err(format!(
+                "expected true or false, found {}",
+                other.describe()
+            )),
+        }
+    }
+
+    /// Integers are seconds, strings may carry a unit: `500ms`, `10s` or `2m`.
+    fn as_duration(&self) -> Result<Duration, String> {
+        let text = match self {
+            Value::Integer(_) => return self.as_u64().map(Duration::from_secs),
+            Value::String(text) => text.trim(),
+            other => return Err(format!("expected a duration, found {}", other.describe())),
+        };
+
+        let split = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("expected a duration like 10s or 500ms, found {:?}", text))?;
+        match unit.trim() {
+            "" | "s" => Ok(Duration::from_secs(number)),
+            "ms" => Ok(Duration::from_millis(number)),
+            "m" => number
+                .checked_mul(60)
+                .map(Duration::from_secs)
+                .ok_or_else(|| format!("{:?} is too long", text)),
+            _ => Err(format!(
+                "unknown duration unit in {:?}, use ms, s or m",
+                text
+            )),
+        }
+    }
+}
+
+/// Parses the subset of TOML used by config files: `[section]` headers and
+/// `key = value` pairs whose values are strings, integers or booleans, with
+/// `#` comments. Returns each value under its `section.key` along with the
+/// line it was found on.
+pub fn parse(text: &str) -> Result<Vec<(String, Value, usize)>, String> {
+    let mut entries: Vec<(String, Value, usize)> = Vec::new();
+    let mut section = String::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let error = |message: String| format!("line {}: {}", number, message);
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix('[') {
+            let (name, after) = rest
+                .split_once(']')
+                .ok_or_else(|| error("unterminated section header".to_string()))?;
+            if !is_comment(after) {
+                return Err(error(format!(
+                    "unexpected text after section: {}",
+                    after.trim()
+                )));
+            }
+            let name = name.trim();
+            if !is_key(name.replace('.', "").as_str()) {
+                return Err(error(format!("invalid section name: {:?}", name)));
+            }
+            section = name.to_string();
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(format!("expected key = value, found {:?}", line)))?;
+        let key = key.trim();
+        if !is_key(key) {
+            return Err(error(format!("invalid key: {:?}", key)));
+        }
+
+        let value = parse_value(value.trim()).map_err(error)?;
+        let key = match section.is_empty() {
+            true => key.to_string(),
+            false => format!("{}.{}", section, key),
+        };
+
+        if entries.iter().any(|(existing, _, _)| *existing == key) {
+            return Err(error(format!("duplicate key: {}", key)));
+        }
+        entries.push((key, value, number));
+    }
+
+    Ok(entries)
+}
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn is_comment(text: &str) -> bool {
+    let text = text.trim();
+    text.is_empty() || text.starts_with('#')
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    if let Some(rest) = text.strip_prefix('"') {
+        return parse_basic_string(rest);
+    }
+
+    if let Some(rest) = text.strip_prefix('\'') {
+        let (value, after) = rest
+            .split_once('\'')
+            .ok_or_else(|| "unterminated string".to_string())?;
+        return match is_comment(after) {
+            true => Ok(Value::String(value.to_string())),
+            false => Err(format!("unexpected text after string: {}", after.trim())),
+        };
+    }
+
+    let token = text.split('#').next().unwrap_or_default().trim();
+    match token {
+        "" => Err("missing value".to_string()),
+        "true" => Ok(Value::Boolean(true)),
+        "false" => Ok(Value::Boolean(false)),
+        _ => token
+            .replace('_', "")
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| format!("invalid value: {} (strings must be quoted)", token)),
+    }
+}
+
+/// Parses a double quoted string after the opening quote, handling escapes.
+fn parse_basic_string(text: &str) -> Result<Value, String> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => {
+                let after = &text[index + 1..];
+                return match is_comment(after) {
+                    true => Ok(Value::String(value)),
+                    false => Err(format!("unexpected text after string: {}", after.trim())),
+                };
+            }
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('"') => value.push('"'),
+                Some('\\') => value.push('\\'),
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(other) => return Err(format!("unsupported escape: \\{}", other)),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+
+    Err("unterminated string".to_string())
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, message)
+}
+
+impl ServerConfig {
+    /// Loads the config for a deployment: the defaults, overridden by the file
+    /// named in `RUST_SERVER_CONFIG` (or `rust-server.toml` if it exists), then
+    /// by `RUST_SERVER_*` environment variables. The result is validated.
+    pub fn load() -> io::Result<ServerConfig> {
+        ServerConfig::load_from(None)
+    }
+
+    /// Like `load`, but reads the given config file instead of looking for one.
+    pub fn load_from(file: Option<&Path>) -> io::Result<ServerConfig> {
+        ServerConfig::load_with(file, std::env::vars())
+    }
+
+    /// Like `load_from`, but reads the given environment variables instead
+    /// of those of the process.
+    pub fn load_with(
+        file: Option<&Path>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> io::Result<ServerConfig> {
+        let mut config = ServerConfig::default();
+        let vars: Vec<(String, String)> = vars.into_iter().collect();
+
+        // an empty variable is the same as an unset one
+        let config_var = format!("{}CONFIG", ENV_PREFIX);
+        let env_file = vars
+            .iter()
+            .find(|(name, value)| *name == config_var && !value.is_empty())
+            .map(|(_, path)| path);
+        match (file, env_file) {
+            (Some(path), _) => config.apply_file(path)?,
+            (None, Some(path)) => config.apply_file(Path::new(&path))?,
+            (None, None) if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                config.apply_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            (None, None) => {}
+        }
+
+        config.apply_env(vars)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Overrides settings with those in a config file.
+    ///
+    /// ```text
+    /// [server]
+    /// host = "0.0.0.0"
+    /// port = 8080
+    /// workers = 8
+    ///
+    /// [static]
+    /// public_dir = "./dist"
+    /// not_found_page = "./dist/404.html"
+    ///
+    /// [timeouts]
+    /// header = "10s"
+    /// keep_alive = 5
+    ///
+    /// [log]
+    /// level = "error"
+    /// ```
+    pub fn apply_file(&mut self, path: &Path) -> io::Result<()> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("could not read config file {}: {}", path.display(), e),
+            )
+        })?;
+        self.apply_entries(&text)
+            .map_err(|e| invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Overrides settings with those in the text of a config file.
+    pub fn apply_toml(&mut self, text: &str) -> io::Result<()> {
+        self.apply_entries(text).map_err(invalid)
+    }
+
+    fn apply_entries(&mut self, text: &str) -> Result<(), String> {
+        let entries = parse(text)?;
+        let mut settings = Settings::new(self);
+        for (key, value, line) in entries {
+            settings
+                .apply(&key, &value)
+                .map_err(|e| format!("line {}: {}: {}", line, key, e))?;
+        }
+        settings.finish();
+        Ok(())
+    }
+
+    /// Overrides a single setting by its file key, e.g. `server.port`, parsing
+    /// and validating the text the same way as an environment variable.
+    pub fn set(&mut self, key: &str, value: &str) -> io::Result<()> {
+        let mut settings = Settings::new(self);
+        settings
+            .apply(key, &Value::String(value.to_string()))
+            .map_err(|e| invalid(format!("{}: {}", key, e)))?;
+        settings.finish();
+        Ok(())
+    }
+
+    /// Overrides settings with `RUST_SERVER_*` variables, e.g. `RUST_SERVER_PORT=80`.
+    /// Unknown variables with the prefix are rejected so typos don't go unnoticed.
+    pub fn apply_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> io::Result<()> {
+        let mut settings = Settings::new(self);
+        for (name, value) in vars {
+            let Some(suffix) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            if suffix == "CONFIG" {
+                continue;
+            }
+
+            let key = SETTINGS
+                .iter()
+                .find(|(_, env)| *env == suffix)
+                .map(|(key, _)| *key)
+                .ok_or_else(|| invalid(format!("unknown environment variable {}", name)))?;
+            settings
+                .apply(key, &Value::String(value))
+                .map_err(|e| invalid(format!("{}: {}", name, e)))?;
+        }
+        settings.finish();
+        Ok(())
+    }
+}
+
+/// Applies settings to a config, collecting the host and port separately
+/// since together they make up the bind address.
+struct Settings<'a> {
+    config: &'a mut ServerConfig,
+    host: Option<String>,
+    port: Option<u16>,
+}
+
+impl<'a> Settings<'a> {
+    fn new(config: &'a mut ServerConfig) -> Self {
+        Settings {
+            config,
+            host: None,
+            port: None,
+        }
+    }
+
+    fn apply(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        let known = SETTINGS
+            .iter()
+            .map(|(known, _)| *known)
+            .find(|known| *known == key)
+            .ok_or_else(|| "unknown setting".to_string())?;
+
+        let config = &mut *self.config;
+        match known {
+            "server.host" => self.host = Some(value.as_string()?),
+            "server.port" => {
+                let port = value.as_u64()?;
+                let port = u16::try_from(port)
+                    .ok()
+                    .filter(|port| *port > 0)
+                    .ok_or_else(|| format!("{} is not a valid port", port))?;
+                self.port = Some(port);
+            }
+            "server.workers" => {
+                config.threads = value.as_usize()?;
+                if config.threads == 0 {
+                    return Err("at least one worker is required".to_string());
+                }
+            }
+            "server.max_queued" => config.max_queued = value.as_usize()?,
+            "server.max_requests_per_connection" => {
+                config.max_requests_per_connection = value.as_usize()?
+            }
+            "server.handle_signals" => config.handle_signals = value.as_bool()?,
+            "static.public_dir" => {
+                let dir = PathBuf::from(value.as_string()?);
+                if !dir.is_dir() {
+                    return Err(format!("{} is not a directory", dir.display()));
+                }
+                config.public_dir = dir;
+            }
+            "static.not_found_page" => {
+                let page = PathBuf::from(value.as_string()?);
+                if !page.is_file() {
+                    return Err(format!("{} is not a file", page.display()));
+                }
+                config.not_found_page = Some(page);
+            }
+            "log.level" => config.log_level = value.as_string()?.parse()?,
+            "timeouts.header" => config.limits.header_timeout = value.as_duration()?,
+            "timeouts.body" => config.limits.body_timeout = value.as_duration()?,
+            "timeouts.write" => config.limits.write_timeout = value.as_duration()?,
+            "timeouts.keep_alive" => config.keep_alive_timeout = value.as_duration()?,
+            "timeouts.shutdown" => config.shutdown_timeout = value.as_duration()?,
+            "limits.max_request_line" => config.limits.max_request_line = value.as_usize()?,
+            "limits.max_header_count" => config.limits.max_header_count = value.as_usize()?,
+            "limits.max_header_size" => config.limits.max_header_size = value.as_usize()?,
+            "limits.max_body_size" => config.limits.max_body_size = value.as_u64()?,
+            _ => unreachable!("setting {} is listed but not applied", known),
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the bind address if the host or port was set, keeping
+    /// whichever part wasn't from the current address.
+    fn finish(self) {
+        if self.host.is_none() && self.port.is_none() {
+            return;
+        }
+
+        let current = self.config.addrs.first().map(String::as_str);
+        let (host, port) = current
+            .and_then(|addr| addr.rsplit_once(':'))
+            .unwrap_or(("localhost", "8080"));
+
+        let host = match self.host {
+            // IPv6 addresses need brackets to be followed by a port
+            Some(host) if host.contains(':') && !host.starts_with('[') => format!("[{}]", host),
+            Some(host) => host,
+            None => host.to_string(),
+        };
+        let port = self
+            .port
+            .map_or_else(|| port.to_string(), |port| port.to_string());
+        self.config.addrs = vec![format!("{}:{}", host, port)];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_sections_values_and_comments() {
+        let entries = parse(
+            "# a comment\n\
+             top = 1\n\
+             \n\
+             [server] # trailing comment\n\
+             host = \"0.0.0.0\" # after a value\n\
+             port = 8_080\n\
+             handle_signals = true\n\
+             \n\
+             [static]\n\
+             public_dir = './dist # not a comment'\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                ("top".to_string(), Value::Integer(1), 2),
+                (
+                    "server.host".to_string(),
+                    Value::String("0.0.0.0".to_string()),
+                    5
+                ),
+                ("server.port".to_string(), Value::Integer(8080), 6),
+                ("server.handle_signals".to_string(), Value::Boolean(true), 7),
+                (
+                    "static.public_dir".to_string(),
+                    Value::String("./dist # not a comment".to_string()),
+                    10
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_escapes_in_basic_strings() {
+        let entries = parse(r#"key = "a \"quoted\" \\ path\n\tend""#).unwrap();
+        assert_eq!(
+            entries[0].1,
+            Value::String("a \"quoted\" \\ path\n\tend".to_string())
+        );
+        assert_eq!(
+            parse(r#"key = "\x""#).unwrap_err(),
+            "line 1: unsupported escape: \\x"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let error = |text: &str| parse(text).unwrap_err();
+        assert_eq!(
+            error("[server]\nport = 1\nport = 2"),
+            "line 3: duplicate key: server.port"
+        );
+        assert_eq!(error("[server"), "line 1: unterminated section header");
+        assert_eq!(
+            error("[bad name]"),
+            "line 1: invalid section name: \"bad name\""
+        );
+        assert_eq!(error("[a] b"), "line 1: unexpected text after section: b");
+        assert_eq!(
+            error("just text"),
+            "line 1: expected key = value, found \"just text\""
+        );
+        assert_eq!(error("bad key = 1"), "line 1: invalid key: \"bad key\"");
+        assert_eq!(error("key ="), "line 1: missing value");
+        assert_eq!(error("key = \"open"), "line 1: unterminated string");
+        assert_eq!(error("key = 'open"), "line 1: unterminated string");
+        assert_eq!(
+            error("key = \"a\" b"),
+            "line 1: unexpected text after string: b"
+        );
+        assert_eq!(
+            error("key = text"),
+            "line 1: invalid value: text (strings must be quoted)"
+        );
+    }
+
+    #[test]
+    fn applies_a_config_file() {
+        let mut config = ServerConfig::default();
+        config
+            .apply_toml(
+                "[server]\nworkers = 3\n\
+                 [timeouts]\nheader = \"500ms\"\nbody = \"2m\"\nkeep_alive = 7\n\
+                 [limits]\nmax_body_size = 1_024\n\
+                 [log]\nlevel = \"error\"\n",
+            )
+            .unwrap();
+
+        assert_eq!(config.threads, 3);
+        assert_eq!(config.limits.header_timeout, Duration::from_millis(500));
+        assert_eq!(config.limits.body_timeout, Duration::from_secs(120));
+        assert_eq!(config.keep_alive_timeout, Duration::from_secs(7));
+        assert_eq!(config.limits.max_body_size, 1024);
+        assert_eq!(config.addrs, ServerConfig::default().addrs);
+
+        let error = |text: &str| ServerConfig::default().apply_toml(text).unwrap_err();
+        assert_eq!(
+            error("[server]\nworkers = 0").to_string(),
+            "line 2: server.workers: at least one worker is required"
+        );
+        assert_eq!(
+            error("[timeouts]\nheader = \"5h\"").to_string(),
+            "line 2: timeouts.header: unknown duration unit in \"5h\", use ms, s or m"
+        );
+        assert_eq!(
+            error("[timeouts]\nshutdown = \"999999999999999999m\"").to_string(),
+            "line 2: timeouts.shutdown: \"999999999999999999m\" is too long"
+        );
+        assert_eq!(
+            error("[server]\nport = 70000").to_string(),
+            "line 2: server.port: 70000 is not a valid port"
+        );
+        assert_eq!(
+            error("[server]\nprot = 80").to_string(),
+            "line 2: server.prot: unknown setting"
+        );
+    }
+
+    #[test]
+    fn applies_environment_variables() {
+        let mut config = ServerConfig::default();
+        config
+            .apply_env(vars(&[
+                ("PATH", "/usr/bin"),
+                ("RUST_SERVER_CONFIG", "ignored.toml"),
+                ("RUST_SERVER_WORKERS", "2"),
+                ("RUST_SERVER_MAX_BODY_SIZE", "10_000"),
+                ("RUST_SERVER_HANDLE_SIGNALS", "yes"),
+                ("RUST_SERVER_SHUTDOWN_TIMEOUT", "3s"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.threads, 2);
+        assert_eq!(config.limits.max_body_size, 10_000);
+        assert!(config.handle_signals);
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn rejects_unknown_and_invalid_environment_variables() {
+        let error = |name: &str, value: &str| {
+            ServerConfig::default()
+                .apply_env(vars(&[(name, value)]))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("RUST_SERVER_PROT", "80"),
+            "unknown environment variable RUST_SERVER_PROT"
+        );
+        assert_eq!(
+            error("RUST_SERVER_PORT", "http"),
+            "RUST_SERVER_PORT: expected a positive integer, found \"http\""
+        );
+        assert_eq!(
+            error("RUST_SERVER_HANDLE_SIGNALS", "maybe"),
+            "RUST_SERVER_HANDLE_SIGNALS: expected true or false, found \"maybe\""
+        );
+    }
+
+    #[test]
+    fn merges_host_and_port_into_the_bind_address() {
+        let mut config = ServerConfig::default();
+        config
+            .apply_env(vars(&[("RUST_SERVER_PORT", "3000")]))
+            .unwrap();
+        assert_eq!(config.addrs, vec!["localhost:3000"]);
+
+        config.set("server.host", "0.0.0.0").unwrap();
+        assert_eq!(config.addrs, vec!["0.0.0.0:3000"]);
+
+        config.set("server.host", "::1").unwrap();
+        assert_eq!(config.addrs, vec!["[::1]:3000"]);
+
+        config.set("server.port", "80").unwrap();
+        assert_eq!(config.addrs, vec!["[::1]:80"]);
+
+        config
+            .apply_toml("[server]\nhost = \"[::]\"\nport = 8443")
+            .unwrap();
+        assert_eq!(config.addrs, vec!["[::]:8443"]);
+    }
+
+    #[test]
+    fn keeps_the_bind_addresses_when_host_and_port_are_unset() {
+        let mut config = ServerConfig {
+            addrs: vec!["0.0.0.0:80".to_string(), "[::]:80".to_string()],
+            ..ServerConfig::default()
+        };
+        config.set("server.workers", "4").unwrap();
+        assert_eq!(config.addrs, vec!["0.0.0.0:80", "[::]:80"]);
+    }
+}
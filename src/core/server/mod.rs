@@ -1,10 +1,28 @@
+pub mod autoindex;
+pub mod builder;
+pub mod config;
+pub mod config_file;
+pub mod pattern;
+pub mod radix;
 pub mod routes;
+#[allow(clippy::module_inception)]
 pub mod server;
+pub mod shutdown;
+pub mod state;
+pub mod static_files;
 pub mod worker;
 
+pub use builder::ServerBuilder;
+pub use config::ServerConfig;
+pub use pattern::RoutePattern;
+pub use radix::RadixTree;
+pub use radix::RouteError;
 pub use routes::RouteActions;
 pub use routes::RouteBuilder;
 pub use routes::RouteHandler;
 pub use routes::Routes;
-pub use server::create_server_on;
 pub use server::Server;
+pub use shutdown::ShutdownHandle;
+pub use state::State;
+pub use static_files::HiddenFiles;
+pub use static_files::StaticFiles;
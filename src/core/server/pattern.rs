@@ -0,0 +1,129 @@
+use crate::core::util;
+use std::cmp::Ordering;
+
+pub type Params = Vec<(String, String)>;
+
+/// A single `/` separated segment of a route pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// Matches the segment exactly, e.g. `users`
+    Static(String),
+    /// Matches any non-empty segment and captures it, e.g. `:id`
+    Param(String),
+    /// Matches the rest of the path and captures it, e.g. `*rest`
+    Wildcard(String),
+}
+
+impl Segment {
+    /// Lower ranks are more specific and take precedence.
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Static(_) => 0,
+            Segment::Param(_) => 1,
+            Segment::Wildcard(_) => 2,
+        }
+    }
+}
+
+/// Route Pattern
+///
+/// A route path made up of static segments, named parameters (`/users/:id`)
+/// and an optional trailing wildcard (`/files/*rest`) which matches the rest
+/// of the path after the slash, even if empty. A bare `*` is a catch-all
+/// which matches every path.
+///
+/// When several patterns match the same path the most specific wins,
+/// comparing segment by segment from the left: static beats parameter
+/// and parameter beats wildcard.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoutePattern {
+    path: String,
+    segments: Vec<Segment>,
+}
+
+impl RoutePattern {
+    pub fn parse(path: &str) -> Result<Self, String> {
+        let trimmed = path.trim_start_matches('/');
+        let parts: Vec<&str> = trimmed.split('/').collect();
+        let mut segments = Vec::with_capacity(parts.len());
+
+        for (i, part) in parts.iter().enumerate() {
+            let segment = if let Some(name) = part.strip_prefix(':') {
+                if name.is_empty() {
+                    return Err(format!("unnamed parameter in route: {}", path));
+                }
+                Segment::Param(name.to_string())
+            } else if let Some(name) = part.strip_prefix('*') {
+                if i != parts.len() - 1 {
+                    return Err(format!("wildcard must be the last segment: {}", path));
+                }
+                Segment::Wildcard(name.to_string())
+            } else {
+                Segment::Static(part.to_string())
+            };
+            segments.push(segment);
+        }
+
+        Ok(RoutePattern {
+            path: path.to_string(),
+            segments,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns true if the pattern has no parameters or wildcards.
+    pub fn is_static(&self) -> bool {
+        self.segments
+            .iter()
+            .all(|segment| matches!(segment, Segment::Static(_)))
+    }
+
+    /// Matches a request path (without the query string), returning the
+    /// percent-decoded captures if it matches.
+    pub fn matches(&self, path: &str) -> Option<Params> {
+        let parts: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        let mut params = Params::new();
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Wildcard(name) => {
+                    if i >= parts.len() {
+                        return None;
+                    }
+                    let rest = parts[i..].join("/");
+                    params.push((name.clone(), util::percent_decode(&rest)));
+                    return Some(params);
+                }
+                Segment::Static(expected) => {
+                    if parts.get(i) != Some(&expected.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => match parts.get(i) {
+                    Some(value) if !value.is_empty() => {
+                        params.push((name.clone(), util::percent_decode(value)));
+                    }
+                    _ => return None,
+                },
+            }
+        }
+
+        match parts.len() == self.segments.len() {
+            true => Some(params),
+            false => None,
+        }
+    }
+
+    /// Orders patterns from most to least specific.
+    pub fn precedence(&self, other: &Self) -> Ordering {
+        let ranks = self.segments.iter().map(Segment::rank);
+        ranks.cmp(other.segments.iter().map(Segment::rank))
+    }
+}
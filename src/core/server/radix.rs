@@ -0,0 +1,445 @@
+use crate::core::server::pattern::{Params, RoutePattern, Segment};
+use crate::core::util;
+use std::fmt;
+
+/// An error registering a route.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RouteError {
+    /// The route pattern could not be parsed.
+    Malformed(String),
+    /// The route overlaps with one which was registered before.
+    Conflict { path: String, reason: String },
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::Malformed(reason) => write!(f, "malformed route: {}", reason),
+            RouteError::Conflict { path, reason } => {
+                write!(f, "route {} conflicts: {}", path, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
+/// A node of the tree. Static children are compressed so that each
+/// holds the longest prefix shared by the routes below it, and no two
+/// static children start with the same character.
+#[derive(Debug)]
+struct Node<T> {
+    prefix: String,
+    value: Option<T>,
+    children: Vec<Node<T>>,
+    param: Option<(String, Box<Node<T>>)>,
+    wildcard: Option<(String, Box<Node<T>>)>,
+}
+
+impl<T> Node<T> {
+    fn new(prefix: &str) -> Self {
+        Node {
+            prefix: prefix.to_string(),
+            value: None,
+            children: Vec::new(),
+            param: None,
+            wildcard: None,
+        }
+    }
+
+    /// Walks (and creates) the static nodes for `text`, which follows this
+    /// node's prefix, splitting existing nodes where the prefixes diverge.
+    fn insert_static(&mut self, text: &str) -> &mut Node<T> {
+        if text.is_empty() {
+            return self;
+        }
+
+        let first = text.chars().next();
+        let index = match self
+            .children
+            .iter()
+            .position(|child| child.prefix.chars().next() == first)
+        {
+            Some(index) => index,
+            None => {
+                self.children.push(Node::new(text));
+                return self.children.last_mut().unwrap();
+            }
+        };
+
+        let child = &mut self.children[index];
+        let common = common_prefix(&child.prefix, text);
+        if common < child.prefix.len() {
+            let mut split = Node::new(&child.prefix[..common]);
+            child.prefix = child.prefix[common..].to_string();
+            std::mem::swap(child, &mut split);
+            child.children.push(split);
+        }
+
+        child.insert_static(&text[common..])
+    }
+
+    /// Returns the parameter or wildcard node with the given name,
+    /// refusing a different name at the same position.
+    fn dynamic_child<'a>(
+        slot: &'a mut Option<(String, Box<Node<T>>)>,
+        name: &str,
+        kind: &str,
+        path: &str,
+    ) -> Result<&'a mut Node<T>, RouteError> {
+        let (existing, node) =
+            slot.get_or_insert_with(|| (name.to_string(), Box::new(Node::new(""))));
+        if existing != name {
+            return Err(RouteError::Conflict {
+                path: path.to_string(),
+                reason: format!("{} {} is already named {}", kind, name, existing),
+            });
+        }
+        Ok(node)
+    }
+
+    /// Matches `path`, which starts after this node's prefix. Static children
+    /// are tried before the parameter, and the parameter before the wildcard,
+    /// backtracking when a more specific branch doesn't lead to an accepted value.
+    fn lookup<'a>(
+        &'a self,
+        path: &str,
+        params: &mut Params,
+        accept: &dyn Fn(&T) -> bool,
+    ) -> Option<&'a T> {
+        if path.is_empty() {
+            if let Some(value) = self.value.as_ref().filter(|value| accept(value)) {
+                return Some(value);
+            }
+        }
+
+        for child in &self.children {
+            if let Some(rest) = path.strip_prefix(child.prefix.as_str()) {
+                if let Some(value) = child.lookup(rest, params, accept) {
+                    return Some(value);
+                }
+            }
+        }
+
+        if let Some((name, node)) = &self.param {
+            let end = path.find('/').unwrap_or(path.len());
+            if end > 0 {
+                params.push((name.clone(), util::percent_decode(&path[..end])));
+                if let Some(value) = node.lookup(&path[end..], params, accept) {
+                    return Some(value);
+                }
+                params.pop();
+            }
+        }
+
+        if let Some((name, node)) = &self.wildcard {
+            if let Some(value) = node.value.as_ref().filter(|value| accept(value)) {
+                params.push((name.clone(), util::percent_decode(path)));
+                return Some(value);
+            }
+        }
+
+        None
+    }
+
+    /// Walks the static nodes for `text` exactly, without matching parameters.
+    fn find_static(&self, text: &str) -> Option<&Node<T>> {
+        if text.is_empty() {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| {
+            let rest = text.strip_prefix(child.prefix.as_str())?;
+            child.find_static(rest)
+        })
+    }
+
+    fn for_each<'a>(&'a self, f: &mut impl FnMut(&'a T)) {
+        if let Some(value) = &self.value {
+            f(value);
+        }
+        self.children.iter().for_each(|child| child.for_each(f));
+        if let Some((_, node)) = &self.param {
+            node.for_each(f);
+        }
+        if let Some((_, node)) = &self.wildcard {
+            node.for_each(f);
+        }
+    }
+}
+
+/// The length in bytes of the longest common prefix, which always ends on
+/// a character boundary so both strings can be split there.
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(a.len().min(b.len()), |((index, _), _)| index)
+}
+
+/// Radix Tree
+///
+/// A compressed prefix tree mapping route patterns to values, matching a
+/// path in time proportional to its length rather than the number of routes.
+/// Patterns may contain named parameters (`/users/:id`) which match a single
+/// non-empty segment, and a trailing wildcard (`/files/*rest`) which matches
+/// the rest of the path. A bare `*` matches every path.
+///
+/// ```
+/// use rust_server::core::server::RadixTree;
+///
+/// let mut tree = RadixTree::new();
+/// tree.insert("/users/:id", "user").unwrap();
+/// tree.insert("/users/new", "new").unwrap();
+///
+/// let (value, params) = tree.lookup("/users/42").unwrap();
+/// assert_eq!(*value, "user");
+/// assert_eq!(params, vec![("id".to_string(), "42".to_string())]);
+/// assert_eq!(*tree.lookup("/users/new").unwrap().0, "new");
+/// ```
+#[derive(Debug)]
+pub struct RadixTree<T> {
+    root: Node<T>,
+}
+
+impl<T> Default for RadixTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> RadixTree<T> {
+    pub fn new() -> Self {
+        RadixTree {
+            root: Node::new(""),
+        }
+    }
+
+    /// Returns the value slot for a pattern, creating the nodes leading to it.
+    /// Fails if the pattern is malformed or names a parameter differently
+    /// from a route registered before at the same position.
+    pub fn entry(&mut self, path: &str) -> Result<&mut Option<T>, RouteError> {
+        let pattern = RoutePattern::parse(path).map_err(RouteError::Malformed)?;
+        let mut node = &mut self.root;
+        let mut text = String::new();
+
+        for segment in pattern.segments() {
+            text.push('/');
+            match segment {
+                Segment::Static(part) => text.push_str(part),
+                Segment::Param(name) => {
+                    let parent = node.insert_static(&text);
+                    node = Node::dynamic_child(&mut parent.param, name, "parameter", path)?;
+                    text.clear();
+                }
+                Segment::Wildcard(name) => {
+                    let parent = node.insert_static(&text);
+                    node = Node::dynamic_child(&mut parent.wildcard, name, "wildcard", path)?;
+                    text.clear();
+                }
+            }
+        }
+
+        Ok(&mut node.insert_static(&text).value)
+    }
+
+    /// Returns the value registered for exactly this pattern, unlike `lookup`
+    /// which matches a request path against every pattern.
+    pub fn get(&self, path: &str) -> Option<&T> {
+        let pattern = RoutePattern::parse(path).ok()?;
+        let mut node = &self.root;
+        let mut text = String::new();
+
+        for segment in pattern.segments() {
+            text.push('/');
+            match segment {
+                Segment::Static(part) => text.push_str(part),
+                Segment::Param(name) | Segment::Wildcard(name) => {
+                    let parent = node.find_static(&text)?;
+                    let slot = match segment {
+                        Segment::Param(_) => &parent.param,
+                        _ => &parent.wildcard,
+                    };
+                    node = match slot {
+                        Some((existing, child)) if existing == name => child,
+                        _ => return None,
+                    };
+                    text.clear();
+                }
+            }
+        }
+
+        node.find_static(&text)?.value.as_ref()
+    }
+
+    /// Inserts a value for the pattern, failing if one is already registered.
+    pub fn insert(&mut self, path: &str, value: T) -> Result<(), RouteError> {
+        let slot = self.entry(path)?;
+        if slot.is_some() {
+            return Err(RouteError::Conflict {
+                path: path.to_string(),
+                reason: "already registered".to_string(),
+            });
+        }
+        *slot = Some(value);
+        Ok(())
+    }
+
+    /// Finds the most specific value matching the path (without a query
+    /// string) along with the percent-decoded parameters it captured.
+    pub fn lookup(&self, path: &str) -> Option<(&T, Params)> {
+        self.lookup_where(path, |_| true)
+    }
+
+    /// Like `lookup`, but skips values which aren't accepted and falls back
+    /// to the next most specific match, e.g. a route for another method.
+    pub fn lookup_where(&self, path: &str, accept: impl Fn(&T) -> bool) -> Option<(&T, Params)> {
+        let mut params = Params::new();
+        let value = self.root.lookup(path, &mut params, &accept)?;
+        Some((value, params))
+    }
+
+    /// Calls `f` with every value in the tree.
+    pub fn for_each<'a>(&'a self, mut f: impl FnMut(&'a T)) {
+        self.root.for_each(&mut f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(patterns: &[&'static str]) -> RadixTree<&'static str> {
+        let mut tree = RadixTree::new();
+        for pattern in patterns {
+            tree.insert(pattern, *pattern).unwrap();
+        }
+        tree
+    }
+
+    fn lookup(tree: &RadixTree<&'static str>, path: &str) -> Option<(&'static str, Params)> {
+        tree.lookup(path).map(|(value, params)| (*value, params))
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Params {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn prefers_static_over_param_over_wildcard() {
+        let tree = tree(&["/files/*rest", "/files/:name", "/files/readme"]);
+
+        assert_eq!(
+            lookup(&tree, "/files/readme"),
+            Some(("/files/readme", params(&[])))
+        );
+        assert_eq!(
+            lookup(&tree, "/files/notes"),
+            Some(("/files/:name", params(&[("name", "notes")])))
+        );
+        assert_eq!(
+            lookup(&tree, "/files/a/b"),
+            Some(("/files/*rest", params(&[("rest", "a/b")])))
+        );
+        assert_eq!(lookup(&tree, "/other"), None);
+    }
+
+    #[test]
+    fn splits_shared_prefixes() {
+        let tree = tree(&["/users", "/user/:id", "/us", "/"]);
+
+        assert_eq!(lookup(&tree, "/users").unwrap().0, "/users");
+        assert_eq!(lookup(&tree, "/us").unwrap().0, "/us");
+        assert_eq!(lookup(&tree, "/").unwrap().0, "/");
+        assert_eq!(lookup(&tree, "/user/7").unwrap().0, "/user/:id");
+        assert_eq!(lookup(&tree, "/use"), None);
+        assert_eq!(lookup(&tree, "/user"), None);
+        assert_eq!(tree.get("/user/:id"), Some(&"/user/:id"));
+        assert_eq!(tree.get("/user/:other"), None);
+    }
+
+    #[test]
+    fn backtracks_to_less_specific_routes() {
+        let tree = tree(&[
+            "/users/new/edit",
+            "/users/:id",
+            "/users/:id/posts",
+            "/*path",
+        ]);
+
+        // the static `new` leads nowhere for this path, so the parameter is tried
+        assert_eq!(
+            lookup(&tree, "/users/new/posts"),
+            Some(("/users/:id/posts", params(&[("id", "new")])))
+        );
+        // neither leads anywhere, so the parameter is dropped for the wildcard
+        assert_eq!(
+            lookup(&tree, "/users/42/likes"),
+            Some(("/*path", params(&[("path", "users/42/likes")])))
+        );
+    }
+
+    #[test]
+    fn skips_values_which_are_not_accepted() {
+        let tree = tree(&["/files/readme", "/files/:name", "*"]);
+
+        let found = tree.lookup_where("/files/readme", |value| *value != "/files/readme");
+        assert_eq!(
+            found,
+            Some((&"/files/:name", params(&[("name", "readme")])))
+        );
+
+        let found = tree.lookup_where("/files/readme", |value| *value == "*");
+        assert_eq!(found.map(|(value, _)| *value), Some("*"));
+
+        assert_eq!(tree.lookup_where("/files/readme", |_| false), None);
+    }
+
+    #[test]
+    fn decodes_captured_params() {
+        let tree = tree(&["/tags/:tag"]);
+        assert_eq!(
+            lookup(&tree, "/tags/caf%C3%A9%20bar"),
+            Some(("/tags/:tag", params(&[("tag", "café bar")])))
+        );
+    }
+
+    #[test]
+    fn refuses_conflicting_routes() {
+        let mut tree = tree(&["/users/:id", "/files/*rest"]);
+
+        assert!(matches!(
+            tree.insert("/users/:name/posts", "posts"),
+            Err(RouteError::Conflict { .. })
+        ));
+        assert!(matches!(
+            tree.insert("/files/*path", "path"),
+            Err(RouteError::Conflict { .. })
+        ));
+        assert!(matches!(
+            tree.insert("/users/:id", "again"),
+            Err(RouteError::Conflict { .. })
+        ));
+        assert!(tree.insert("/users/:id/posts", "posts").is_ok());
+    }
+
+    #[test]
+    fn splits_non_ascii_prefixes_on_character_boundaries() {
+        // é and è share their first byte in UTF-8
+        let tree = tree(&["/é", "/è", "/café", "/cafè/:id", "/日本", "/日本語"]);
+
+        assert_eq!(lookup(&tree, "/é").unwrap().0, "/é");
+        assert_eq!(lookup(&tree, "/è").unwrap().0, "/è");
+        assert_eq!(lookup(&tree, "/café").unwrap().0, "/café");
+        assert_eq!(
+            lookup(&tree, "/cafè/1"),
+            Some(("/cafè/:id", params(&[("id", "1")])))
+        );
+        assert_eq!(lookup(&tree, "/日本").unwrap().0, "/日本");
+        assert_eq!(lookup(&tree, "/日本語").unwrap().0, "/日本語");
+        assert_eq!(lookup(&tree, "/日"), None);
+        assert_eq!(lookup(&tree, "/caf"), None);
+    }
+}
@@ -1,51 +1,167 @@
+use crate::core::data::log::info;
+use crate::core::http::HttpResponse;
 use crate::core::http::Method;
 use crate::core::http::Request;
+use crate::core::middleware::middleware;
+use crate::core::middleware::{Middleware, MiddlewareStack, Next};
+use crate::core::server::radix::{RadixTree, RouteError};
+use crate::core::server::static_files::StaticFiles;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-pub type RouteActions = Result<u8, std::io::Error>;
-pub type RouteHandler = fn(&mut Request) -> RouteActions;
+pub type RouteActions = Result<u16, std::io::Error>;
+pub type RouteHandler = Arc<dyn Fn(&mut Request) -> RouteActions + Send + Sync>;
 
-pub type MethodMap = HashMap<String, RouteHandler>;
-pub type RoutesMap = HashMap<Method, MethodMap>;
+/// The handlers registered for a single route pattern, keyed by method.
+pub type MethodMap = HashMap<Method, RouteHandler>;
+
+/// Default handler when no route matches the path.
+pub fn not_found(request: &mut Request) -> RouteActions {
+    request.send(HttpResponse::not_found().text("Not Found"))
+}
+
+/// Default handler when routes match the path but not the method,
+/// the `Allow` header lists the methods which do match.
+pub fn method_not_allowed(request: &mut Request) -> RouteActions {
+    let allow = request
+        .allowed_methods()
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    request.send(
+        HttpResponse::new(405)
+            .header("Allow", allow)
+            .text("Method Not Allowed"),
+    )
+}
 
 /// Routes
 ///
-/// A collection of route handlers.
+/// A collection of route handlers, stored in a radix tree keyed by path
+/// with the handlers for each method at the node of the path.
 ///
 pub struct Routes {
-    routes: RoutesMap,
+    tree: RadixTree<MethodMap>,
+    not_found: RouteHandler,
+    method_not_allowed: RouteHandler,
+    middleware: MiddlewareStack,
 }
 
-unsafe impl Send for Routes {}
-unsafe impl Sync for Routes {}
+impl Default for Routes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Routes {
     pub fn new() -> Self {
         Routes {
-            routes: HashMap::new(),
+            tree: RadixTree::new(),
+            not_found: Arc::new(not_found),
+            method_not_allowed: Arc::new(method_not_allowed),
+            middleware: MiddlewareStack::new(),
+        }
+    }
+
+    /// Routes the request through the global middleware to its handler.
+    /// Responses sent with `Request::send` are held until the pipeline
+    /// returns so middleware can post-process them, then written.
+    pub fn handle(&self, request: &mut Request) -> RouteActions {
+        let handler = self.find(request);
+        request.defer_response(true);
+        let result = Next::new(&self.middleware, &handler).run(request);
+        request.defer_response(false);
+        let status = result?;
+        Ok(request.flush_response()?.unwrap_or(status))
+    }
+
+    /// Adds middleware which runs for every request, including those
+    /// answered by the not found and method not allowed handlers.
+    pub fn use_middleware(&mut self, middleware: impl Middleware) {
+        self.middleware.push(Arc::new(middleware));
+    }
+
+    /// Finds the handler for the request and stores any captured
+    /// path parameters on the request.
+    ///
+    /// If no route matches the path the not found handler is returned, if
+    /// routes match the path but not the method the method not allowed
+    /// handler is returned and the matching methods are set on the request.
+    ///
+    /// A `HEAD` request without a `HEAD` route is handled by the `GET`
+    /// route, `Request::send` then leaves out the body.
+    pub fn find(&self, request: &mut Request) -> RouteHandler {
+        let method = Method::from(&request.method);
+        let path = request.path().to_string();
+
+        let mut methods = vec![method.clone()];
+        if method == Method::from("HEAD") {
+            methods.push(Method::GET);
+        }
+        for method in &methods {
+            if let Some((handlers, params)) = self
+                .tree
+                .lookup_where(&path, |handlers| handlers.contains_key(method))
+            {
+                request.set_params(params);
+                return handlers[method].clone();
+            }
+        }
+
+        let allowed = self.allowed_methods(&path);
+        if allowed.is_empty() {
+            info!("[routes] no route found for: {}", request.uri);
+            return self.not_found.clone();
         }
+
+        info!("[routes] {} not allowed for: {}", method, request.uri);
+        request.set_allowed_methods(allowed);
+        self.method_not_allowed.clone()
     }
 
-    pub fn find(&self, request: &mut Request) -> Option<RouteHandler> {
-        match self.routes.get(&Method::from(&request.method)) {
-            Some(method_map) => match method_map.get(&request.uri) {
-                Some(handler) => Some(*handler),
-                None => match method_map.get("*") {
-                    Some(handler) => Some(*handler),
-                    None => None,
-                },
-            },
-            None => match self.routes.get(&Method::GET) {
-                Some(method_map) => match method_map.get("*") {
-                    Some(handler) => Some(*handler),
-                    None => None,
-                },
-                None => {
-                    println!("[routes] no route found for: {}", request.uri);
-                    None
+    /// Returns every method with a route matching the path, sorted by name,
+    /// including `HEAD` wherever `GET` is allowed.
+    pub fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        let mut methods = Vec::new();
+        self.tree.for_each(|handlers| {
+            for method in handlers.keys() {
+                if !methods.contains(method) {
+                    methods.push(method.clone());
                 }
-            },
+            }
+        });
+
+        methods.retain(|method| {
+            self.tree
+                .lookup_where(path, |handlers| handlers.contains_key(method))
+                .is_some()
+        });
+        let head = Method::from("HEAD");
+        if methods.contains(&Method::GET) && !methods.contains(&head) {
+            methods.push(head);
+        }
+        methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        methods
+    }
+
+    /// Registers a handler, failing if the pattern is malformed or the
+    /// method and pattern conflict with an existing route.
+    pub fn insert(
+        &mut self,
+        method: Method,
+        path: &str,
+        handler: RouteHandler,
+    ) -> Result<(), RouteError> {
+        let handlers = self.tree.entry(path)?.get_or_insert_with(MethodMap::new);
+        if handlers.contains_key(&method) {
+            return Err(RouteError::Conflict {
+                path: path.to_string(),
+                reason: format!("{:?} is already registered", method),
+            });
         }
+        handlers.insert(method, handler);
+        Ok(())
     }
 
     /// Allows you to configure routes in a closure using
@@ -65,17 +181,185 @@ impl Routes {
 /// Exposed as part of the Routes API.
 pub struct RouteBuilder<'a> {
     build: &'a mut Routes,
+    prefix: String,
+    middleware: MiddlewareStack,
 }
 
 impl<'a> RouteBuilder<'a> {
     pub fn new(routes: &'a mut Routes) -> Self {
-        RouteBuilder { build: routes }
+        RouteBuilder {
+            build: routes,
+            prefix: String::new(),
+            middleware: MiddlewareStack::new(),
+        }
     }
 
-    pub fn def(&mut self, method: &str, path: &str, handler: RouteHandler) -> &mut Self {
-        let method = Method::from(method);
-        let method_map = self.build.routes.entry(method).or_insert(HashMap::new());
-        method_map.insert(path.to_string(), handler);
+    /// Defines a group of routes sharing a path prefix. Groups can be nested,
+    /// and middleware added inside a group only applies to its routes, after
+    /// the middleware the group inherited from its parent.
+    ///
+    /// ```
+    /// use rust_server::core::http::HttpResponse;
+    /// use rust_server::core::server::Routes;
+    /// use rust_server::core::Request;
+    ///
+    /// let mut routes = Routes::new();
+    /// routes.configure(|route| {
+    ///     route.group("/api/v1", |api| {
+    ///         api.def("GET", "/users/:id", |request: &mut Request| {
+    ///             request.send(HttpResponse::ok().text("user"))
+    ///         });
+    ///     });
+    /// });
+    ///
+    /// assert_eq!(routes.allowed_methods("/api/v1/users/42").len(), 2);
+    /// ```
+    pub fn group<F>(&mut self, prefix: &str, f: F) -> &mut Self
+    where
+        F: FnOnce(&mut RouteBuilder),
+    {
+        let mut group = RouteBuilder {
+            prefix: self.path(prefix).trim_end_matches('/').to_string(),
+            middleware: self.middleware.clone(),
+            build: &mut *self.build,
+        };
+        f(&mut group);
+        self
+    }
+
+    /// Joins the group prefix with a path, so `/` in a group is the prefix
+    /// itself and a bare `*` catches everything below it.
+    fn path(&self, path: &str) -> String {
+        let path = path.trim_start_matches('/');
+        match (self.prefix.is_empty(), path.is_empty()) {
+            (true, true) => "/".to_string(),
+            (false, true) => self.prefix.clone(),
+            _ => format!("{}/{}", self.prefix, path),
+        }
+    }
+
+    /// Adds middleware which runs for the routes defined after this call
+    /// with this builder, unlike `Server::use_middleware` it doesn't run
+    /// for requests which don't match one of those routes.
+    pub fn use_middleware(&mut self, middleware: impl Middleware) -> &mut Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Defines a route for the method and path pattern, which may contain
+    /// named parameters (`/users/:id`) and a trailing wildcard (`/files/*rest`).
+    /// The handler can be a function or a closure capturing its own state.
+    ///
+    /// Panics if the pattern is malformed or conflicts with an existing route,
+    /// use `try_def` to handle the error instead.
+    pub fn def<F>(&mut self, method: &str, path: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Request) -> RouteActions + Send + Sync + 'static,
+    {
+        if let Err(e) = self.try_def(method, path, handler) {
+            panic!("[routes] {}", e);
+        }
+        self
+    }
+
+    /// Defines a route, returning an error if the pattern is malformed or
+    /// conflicts with an existing route.
+    pub fn try_def<F>(
+        &mut self,
+        method: &str,
+        path: &str,
+        handler: F,
+    ) -> Result<&mut Self, RouteError>
+    where
+        F: Fn(&mut Request) -> RouteActions + Send + Sync + 'static,
+    {
+        self.try_def_with(method, path, MiddlewareStack::new(), handler)
+    }
+
+    /// Defines a route with middleware which only runs for this route, after
+    /// any middleware added to the builder with `use_middleware`.
+    ///
+    /// Panics if the pattern is malformed or conflicts with an existing route.
+    pub fn def_with<F>(
+        &mut self,
+        method: &str,
+        path: &str,
+        middleware: MiddlewareStack,
+        handler: F,
+    ) -> &mut Self
+    where
+        F: Fn(&mut Request) -> RouteActions + Send + Sync + 'static,
+    {
+        if let Err(e) = self.try_def_with(method, path, middleware, handler) {
+            panic!("[routes] {}", e);
+        }
+        self
+    }
+
+    /// Defines a route with its own middleware, returning an error if the
+    /// pattern is malformed or conflicts with an existing route.
+    pub fn try_def_with<F>(
+        &mut self,
+        method: &str,
+        path: &str,
+        middleware: MiddlewareStack,
+        handler: F,
+    ) -> Result<&mut Self, RouteError>
+    where
+        F: Fn(&mut Request) -> RouteActions + Send + Sync + 'static,
+    {
+        let stack = self.middleware.iter().cloned().chain(middleware).collect();
+        let handler = middleware::wrap(stack, Arc::new(handler));
+        let path = match self.prefix.is_empty() {
+            true => path.to_string(),
+            false => self.path(path),
+        };
+        self.build.insert(Method::from(method), &path, handler)?;
+        Ok(self)
+    }
+
+    /// Serves a directory of static files below `prefix` for `GET` and `HEAD`
+    /// requests, see `StaticFiles`. Mounting at `/` catches every path which
+    /// no other route matches.
+    ///
+    /// Panics if the prefix conflicts with an existing route.
+    pub fn mount(&mut self, prefix: &str, files: StaticFiles) -> &mut Self {
+        let prefix = prefix.trim_end_matches('/');
+        let mount = self.path(prefix).trim_end_matches('/').to_string();
+        let files = Arc::new(files);
+        let handler = move |request: &mut Request| {
+            let path = request.path().strip_prefix(mount.as_str()).unwrap_or("");
+            let path = path.to_string();
+            files.serve(request, &path)
+        };
+
+        for method in ["GET", "HEAD"] {
+            self.def(method, &format!("{}/*", prefix), handler.clone());
+            if !prefix.is_empty() {
+                self.def(method, prefix, handler.clone());
+            }
+        }
+        self
+    }
+
+    /// Sets the handler used when no route matches the path, which
+    /// defaults to a plain `404 Not Found`.
+    pub fn not_found<F>(&mut self, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Request) -> RouteActions + Send + Sync + 'static,
+    {
+        self.build.not_found = Arc::new(handler);
+        self
+    }
+
+    /// Sets the handler used when routes match the path but not the method,
+    /// which defaults to `405 Method Not Allowed` with an `Allow` header.
+    /// The matching methods are available from `Request::allowed_methods`.
+    pub fn method_not_allowed<F>(&mut self, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Request) -> RouteActions + Send + Sync + 'static,
+    {
+        self.build.method_not_allowed = Arc::new(handler);
         self
     }
 }
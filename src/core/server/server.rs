@@ -1,125 +1,390 @@
+use crate::core::data::log::{self, error, info};
+use crate::core::http::status::reason_phrase;
+use crate::core::http::{Connection, HttpCodec, HttpError, HttpResponse, Limits, Readiness};
+use crate::core::middleware::Middleware;
 use crate::core::server::routes::RouteBuilder;
+use crate::core::server::RouteActions;
+use crate::core::server::State;
 use crate::core::tcp_methods::TcpMethods;
+use crate::core::util::{NotFoundPage, PublicDir};
 use crate::core::ArcRwLock;
 use crate::core::Request;
 use crate::core::Routes;
 use crate::core::ThreadSafe;
 
-use std::cell::RefCell;
-use std::io::{Error, Write};
-use std::net::{TcpListener, TcpStream};
+use std::io::{Error, ErrorKind, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
 use std::rc::Rc;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 
-use super::worker::Message;
-use super::worker::Worker;
+use super::builder::ServerBuilder;
+use super::config::ServerConfig;
+use super::shutdown::{self, InFlight, ShutdownHandle};
+use super::worker::WorkerPool;
 
-static NUM_WORKERS: usize = 4;
+/// How long the accept loop sleeps when there is nothing to do
+static POLL_INTERVAL: Duration = Duration::from_millis(2);
 
-/// Convenience Init
-pub fn create_server_on(port: u16) -> Server {
-    Server::new(&format!("localhost:{}", port)).unwrap()
-}
+/// How long the accept loop may block writing a 503 to a rejected client
+static REJECT_WRITE_TIMEOUT: Duration = Duration::from_millis(50);
 
 /// Server module
 pub struct Server {
-    listener: TcpListener,
-    pub routes: Routes,
-    workers: Vec<Worker>,
-    receiver: Arc<Mutex<Receiver<Message>>>,
-    channel: Sender<Message>,
-    worker_id: RefCell<usize>,
-    connections: Vec<TcpStream>,
+    listeners: Vec<TcpListener>,
+    config: ServerConfig,
+    routes: Arc<Routes>,
+    state: Arc<State>,
+    pool: Option<WorkerPool>,
+    connections: Vec<Connection>,
+    idle_sender: Sender<Connection>,
+    idle_receiver: Receiver<Connection>,
+    shutdown: ShutdownHandle,
+    in_flight: InFlight,
 }
 
 impl Server {
-    /// Create a new server instance with a TcpListener
-    /// listening on `localhost:8080`
+    /// Create a new server listening on a single address with the default
+    /// config, use `Server::builder` to configure anything else.
     pub fn new(addr: &str) -> Result<Self, std::io::Error> {
-        println!("[server] binding to address: http://{}", addr);
-        let listener = TcpListener::bind(addr)?;
-        let routes = Routes::new();
+        Server::builder().bind(addr).build()
+    }
+
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    /// Validates the config and binds every address.
+    pub fn from_config(config: ServerConfig) -> Result<Self, std::io::Error> {
+        config.validate()?;
+        log::set_level(config.log_level);
 
-        // Create channel for worker communication
+        let listeners = config
+            .addrs
+            .iter()
+            .map(|addr| {
+                info!("[server] binding to address: http://{}", addr);
+                let listener = TcpListener::bind(addr)
+                    .map_err(|e| Error::new(e.kind(), format!("could not bind {}: {}", addr, e)))?;
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        if config.handle_signals {
+            shutdown::handle_signals();
+        }
+
+        let mut state = State::new();
+        state.insert(PublicDir(config.public_dir.clone()));
+        if let Some(page) = &config.not_found_page {
+            state.insert(NotFoundPage(page.clone()));
+        }
 
-        // Create workers
-        let (sender, receiver) = std::sync::mpsc::channel();
-        let receiver = Arc::new(Mutex::new(receiver));
-        let workers: Vec<Worker> = (0..NUM_WORKERS).map(|id| Worker::new(id)).collect();
+        // Idle keep-alive connections are handed back to the accept loop
+        let (idle_sender, idle_receiver) = std::sync::mpsc::channel();
 
         Ok(Server {
-            listener,
-            routes,
-            workers,
-            receiver,
-            channel: sender,
-            worker_id: RefCell::new(0),
+            listeners,
+            config,
+            routes: Arc::new(Routes::new()),
+            state: Arc::new(state),
+            pool: None,
             connections: vec![],
+            idle_sender,
+            idle_receiver,
+            shutdown: ShutdownHandle::new(),
+            in_flight: InFlight::default(),
         })
     }
 
     /// Start the server
+    ///
+    /// The accept loop also watches idle keep-alive connections and
+    /// hands them back to a worker as soon as the next request arrives.
+    ///
+    /// Runs until the server is shut down through a `ShutdownHandle` (or a
+    /// signal, see `ServerBuilder::handle_signals`), then drains in-flight
+    /// requests, stops the workers and returns. A server can't be started
+    /// again afterwards.
     pub fn start(&mut self) {
-        println!("[server] starting server...");
-        loop {
-            let stream = match self.listener.accept() {
-                Ok((stream, _)) => stream,
-                Err(e) => {
-                    eprintln!("[server] error accepting connection: {:?}", e);
-                    continue;
-                }
-            };
+        info!(
+            "[server] starting server with {} workers...",
+            self.config.threads
+        );
+        self.pool = Some(WorkerPool::new(self.config.threads));
+        while !self.shutdown_requested() {
+            let mut active = false;
 
-            match self.distribute(stream) {
-                Ok(_) => (),
-                Err(e) => {
-                    eprintln!("[server] error distributing connection: {:?}", e);
+            for index in 0..self.listeners.len() {
+                match self.listeners[index].accept() {
+                    Ok((stream, _)) => {
+                        active = true;
+                        if let Err(e) = self.distribute(stream) {
+                            error!("[server] error distributing connection: {:?}", e);
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => {
+                        error!("[server] error accepting connection: {:?}", e);
+                    }
                 }
             }
+
+            while let Ok(mut connection) = self.idle_receiver.try_recv() {
+                connection.touch();
+                self.connections.push(connection);
+            }
+
+            active |= self.poll_connections();
+
+            if !active {
+                thread::sleep(POLL_INTERVAL);
+            }
         }
+
+        self.drain();
     }
 
-    fn distribute(&mut self, stream: TcpStream) -> Result<(), std::io::Error> {
-        println!("[server] {} {}", "-".repeat(40), "+");
-
-        if stream.is_keep_alive() {
-            println!("[server] keep-alive connection");
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "keep-alive connection",
-            ));
-        } else {
-            println!("[server] connecting {}", stream.peer_addr().unwrap());
+    fn shutdown_requested(&self) -> bool {
+        self.shutdown.is_shutdown() || (self.config.handle_signals && shutdown::signal_received())
+    }
+
+    /// Stops accepting work, waits for in-flight requests to finish up to
+    /// the shutdown timeout, then stops and joins the workers. Workers still
+    /// busy after the timeout are left to finish on their own.
+    fn drain(&mut self) {
+        info!("[server] shutting down...");
+        self.shutdown.shutdown();
+        self.connections.clear();
+
+        let deadline = Instant::now() + self.config.shutdown_timeout;
+        while self.in_flight.count() > 0 && Instant::now() < deadline {
+            // connections going idle while draining are closed by dropping them
+            while self.idle_receiver.try_recv().is_ok() {}
+            thread::sleep(POLL_INTERVAL);
         }
 
-        let mut request = Request::from(stream)?;
-        let handler = match self.routes.find(&mut request) {
-            Some(handler) => handler,
-            None => {
-                println!("[server] no route found for: {}", request.uri);
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    "no route found",
-                ));
-            }
+        let Some(pool) = self.pool.take() else {
+            return;
         };
 
-        let operation = Box::new(move || handler(&mut request));
-        let worker_id = self.get_worker_id();
-        self.workers[worker_id].enqueue(operation);
+        let remaining = self.in_flight.count();
+        if remaining > 0 {
+            // connections still waiting for a worker are closed without a response
+            pool.clear();
+            pool.shutdown();
+            error!(
+                "[server] shutdown timed out with {} connection(s) in flight",
+                remaining
+            );
+            return;
+        }
+
+        pool.shutdown();
+        pool.join();
+        info!("[server] shut down");
+    }
+
+    /// Returns a handle which stops the server from another thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Hands a new connection straight to the worker pool. Nothing is read
+    /// here, so a client which connects and stays silent only ties up the
+    /// worker waiting on it until the read timeout, never the accept loop.
+    fn distribute(&mut self, stream: TcpStream) -> Result<(), std::io::Error> {
+        info!("[server] {} +", "-".repeat(40));
+        info!("[server] connecting {}", stream.peer_addr()?);
+
+        // accepted sockets may inherit non-blocking mode from the listener
+        stream.set_nonblocking(false)?;
+
+        let connection = Connection::new(
+            stream,
+            self.config.max_requests_per_connection,
+            self.config.limits,
+        );
+        self.dispatch(connection);
         Ok(())
     }
 
-    fn get_worker_id(&self) -> usize {
-        let worker_id = self.worker_id.take();
-        *self.worker_id.borrow_mut() = (worker_id + 1) % NUM_WORKERS;
-        worker_id
+    /// Checks every idle connection, dispatching those with a new request
+    /// and dropping those which closed or timed out. Returns true if any
+    /// connection was dispatched.
+    fn poll_connections(&mut self) -> bool {
+        let mut dispatched = false;
+        for connection in std::mem::take(&mut self.connections) {
+            match connection.poll() {
+                Readiness::Ready => {
+                    dispatched = true;
+                    self.dispatch(connection);
+                }
+                Readiness::Idle if connection.idle_for() < self.config.keep_alive_timeout => {
+                    self.connections.push(connection);
+                }
+                Readiness::Idle | Readiness::Closed => {}
+            }
+        }
+        dispatched
     }
 
-    pub fn configure(&mut self, f: impl FnOnce(&mut RouteBuilder) -> ()) {
-        self.routes.configure(f);
+    /// Queues a connection for the next free worker, which reads and
+    /// routes its requests.
+    fn dispatch(&mut self, connection: Connection) {
+        let Some(pool) = &self.pool else {
+            return;
+        };
+        if pool.queued() >= self.config.max_queued {
+            reject(connection);
+            return;
+        }
+
+        let routes = self.routes.clone();
+        let state = self.state.clone();
+        let idle = self.idle_sender.clone();
+        let shutdown = self.shutdown.clone();
+        let guard = self.in_flight.start();
+        let operation = Box::new(move || {
+            let _guard = guard;
+            serve(&routes, &state, connection, &idle, &shutdown)
+        });
+        pool.execute(operation);
+    }
+
+    /// Configure the routes, must be called before `start`.
+    pub fn configure(&mut self, f: impl FnOnce(&mut RouteBuilder)) {
+        Arc::get_mut(&mut self.routes)
+            .expect("routes must be configured before the server starts")
+            .configure(f);
+    }
+
+    /// Adds middleware which runs for every request in the order added,
+    /// before the route handler. Must be called before `start`.
+    pub fn use_middleware(&mut self, middleware: impl Middleware) -> &mut Self {
+        Arc::get_mut(&mut self.routes)
+            .expect("middleware must be added before the server starts")
+            .use_middleware(middleware);
+        self
+    }
+
+    /// Adds a value to the application state shared with every handler,
+    /// which is retrieved by type with `Request::state`. Must be called
+    /// before `start`, use a `ThreadSafe` value for mutable state.
+    pub fn manage<T: Send + Sync + 'static>(&mut self, value: T) -> &mut Self {
+        Arc::get_mut(&mut self.state)
+            .expect("state must be added before the server starts")
+            .insert(value);
+        self
+    }
+
+    /// The first address the server is listening on, useful when binding to port 0.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listeners[0].local_addr()
+    }
+
+    /// Every address the server is listening on.
+    pub fn local_addrs(&self) -> std::io::Result<Vec<SocketAddr>> {
+        self.listeners.iter().map(TcpListener::local_addr).collect()
+    }
+
+    pub fn routes(&self) -> &Routes {
+        &self.routes
+    }
+}
+
+/// Turns a connection away when every worker is busy and the queue is full,
+/// telling the client to retry rather than leaving it waiting.
+///
+/// This runs on the accept loop, so the write gets a short timeout and a
+/// client which doesn't read is simply dropped instead of stalling it.
+fn reject(connection: Connection) {
+    error!("[server] workers saturated, answering 503");
+    if let Err(e) = connection
+        .stream()
+        .set_write_timeout(Some(REJECT_WRITE_TIMEOUT))
+    {
+        error!("[server] error rejecting connection: {:?}", e);
+        return;
+    }
+    close_with(
+        &connection,
+        HttpResponse::new(503).header("Retry-After", "1"),
+    );
+}
+
+/// Answers a request which couldn't be read with the status its error
+/// carries, or returns the error if the connection itself failed.
+fn refuse(connection: &Connection, error: Error) -> RouteActions {
+    let status = HttpError::status_of(&error).ok_or(error)?;
+    error!("[server] refusing request with {}", status);
+    close_with(
+        connection,
+        HttpResponse::new(status).text(reason_phrase(status)),
+    );
+    Ok(status)
+}
+
+/// Sends a final response and closes the connection.
+fn close_with(connection: &Connection, response: HttpResponse) {
+    let response = response.header("Connection", "close");
+    let mut stream = connection.stream();
+    if response.encode_to(&mut stream).is_ok() {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Serves requests on a connection for as long as the client keeps sending
+/// them back to back. Once the connection goes idle it is sent back to the
+/// accept loop, so an idle client never holds on to a worker.
+///
+/// Pipelined requests are read from the connection's buffer one at a time
+/// and each response is written before the next request is parsed, so
+/// responses always go out in the order the requests were received.
+fn serve(
+    routes: &Routes,
+    state: &Arc<State>,
+    mut connection: Connection,
+    idle: &Sender<Connection>,
+    shutdown: &ShutdownHandle,
+) -> RouteActions {
+    let write_timeout = connection.limits().write_timeout;
+    connection.stream().set_write_timeout(Some(write_timeout))?;
+
+    loop {
+        let mut request = match connection.next_request() {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(0),
+            Err(e) => return refuse(&connection, e),
+        };
+
+        request.set_state(state.clone());
+        if shutdown.is_shutdown() {
+            request.set_keep_alive(false);
+        }
+        let status = routes.handle(&mut request)?;
+
+        if !request.keep_alive() || shutdown.is_shutdown() {
+            request.close()?;
+            return Ok(status);
+        }
+
+        match connection.poll() {
+            Readiness::Ready => continue,
+            Readiness::Idle => {
+                // the accept loop may have shut down, in which case the connection is dropped
+                let _ = idle.send(connection);
+                return Ok(status);
+            }
+            Readiness::Closed => return Ok(status),
+        }
     }
 }
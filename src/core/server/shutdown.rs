@@ -0,0 +1,104 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Set from the signal handler, which may only touch atomics.
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// Shutdown Handle
+///
+/// Stops a running `Server` from another thread. The server stops accepting
+/// connections, finishes the requests already in flight (up to its shutdown
+/// timeout), stops its workers and returns from `start`.
+///
+/// ```no_run
+/// use rust_server::core::server::Server;
+/// use std::thread;
+///
+/// let mut server = Server::new("127.0.0.1:8080").unwrap();
+/// let handle = server.shutdown_handle();
+///
+/// thread::spawn(move || server.start());
+/// handle.shutdown();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownHandle {
+    flag: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        ShutdownHandle {
+            flag: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Asks the server to shut down, returning immediately.
+    pub fn shutdown(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+}
+
+/// Counts the connections queued on or being served by a worker.
+#[derive(Clone, Debug, Default)]
+pub struct InFlight {
+    count: Arc<AtomicUsize>,
+}
+
+impl InFlight {
+    /// Counts a connection until the returned guard is dropped, which
+    /// also happens if its handler panics.
+    pub fn start(&self) -> InFlightGuard {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard {
+            count: self.count.clone(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+}
+
+pub struct InFlightGuard {
+    count: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Installs handlers for SIGINT and SIGTERM which record the signal
+/// instead of terminating the process. Does nothing on other platforms.
+pub fn handle_signals() {
+    #[cfg(unix)]
+    unsafe {
+        signal(SIGINT, on_signal);
+        signal(SIGTERM, on_signal);
+    }
+}
+
+/// Returns true once SIGINT or SIGTERM has been received.
+pub fn signal_received() -> bool {
+    SIGNALLED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+const SIGINT: i32 = 2;
+#[cfg(unix)]
+const SIGTERM: i32 = 15;
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(_signum: i32) {
+    SIGNALLED.store(true, Ordering::SeqCst);
+}
@@ -0,0 +1,58 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// State
+///
+/// Application state owned by the `Server` and shared with every handler,
+/// keyed by type so each handler can ask for exactly what it needs. Values
+/// are read-only, wrap them in a `ThreadSafe` for shared mutable state.
+///
+/// ```
+/// use rust_server::core::server::State;
+/// use rust_server::core::{ArcRwLock, ThreadSafe};
+///
+/// let mut state = State::new();
+/// state.insert(ThreadSafe::new(0_u64));
+///
+/// let counter = state.get::<ThreadSafe<u64>>().unwrap();
+/// counter.write(|count| *count += 1);
+/// assert_eq!(counter.read(|count| *count), 1);
+/// ```
+#[derive(Clone, Default)]
+pub struct State {
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl State {
+    pub fn new() -> Self {
+        State {
+            values: HashMap::new(),
+        }
+    }
+
+    /// Stores a value, replacing any previous value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.values.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Returns the value of the given type, if one was stored.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .cloned()
+            .and_then(|value| value.downcast::<T>().ok())
+    }
+
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+}
+
+impl std::fmt::Debug for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("State")
+            .field("values", &self.values.len())
+            .finish()
+    }
+}
@@ -0,0 +1,343 @@
+use crate::core::data::mime::is_compressible;
+use crate::core::get_mime_type;
+use crate::core::http::{ContentEncoding, HttpResponse};
+use crate::core::middleware::Compression;
+use crate::core::server::autoindex::{self, Sort};
+use crate::core::server::RouteActions;
+use crate::core::util;
+use crate::core::Request;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What a `StaticFiles` mount does with paths containing a segment which
+/// starts with a dot, like `/.env` or `/.git/config`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HiddenFiles {
+    /// Treat them as missing.
+    #[default]
+    Ignore,
+    /// Answer with `403 Forbidden`.
+    Deny,
+    /// Serve them like any other file.
+    Allow,
+}
+
+/// Static Files
+///
+/// Serves a directory, mounted at a path prefix with `RouteBuilder::mount`.
+/// Paths are resolved with `util::resolve_path`, so nothing outside the root
+/// is ever served.
+///
+/// ```
+/// use rust_server::core::server::{HiddenFiles, Routes, StaticFiles};
+///
+/// let mut routes = Routes::new();
+/// routes.configure(|route| {
+///     route.mount("/assets", StaticFiles::new("./build/assets"));
+///     route.mount(
+///         "/docs",
+///         StaticFiles::new("./site")
+///             .index(["index.html", "README.html"])
+///             .hidden_files(HiddenFiles::Deny),
+///     );
+/// });
+///
+/// assert_eq!(routes.allowed_methods("/assets/app.js").len(), 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct StaticFiles {
+    root: PathBuf,
+    index: Vec<String>,
+    fallback: Option<PathBuf>,
+    not_found: Option<PathBuf>,
+    hidden: HiddenFiles,
+    redirect_trailing_slash: bool,
+    headers: Vec<(String, String)>,
+    cache_rules: Vec<(String, String)>,
+    compression: Option<Compression>,
+    autoindex: bool,
+}
+
+impl StaticFiles {
+    /// Serves the files under `root`, with `index.html` as the index file
+    /// and `404.html` as the not found page if they exist.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        StaticFiles {
+            root: root.into(),
+            index: vec!["index.html".to_string()],
+            fallback: None,
+            not_found: Some(PathBuf::from("404.html")),
+            hidden: HiddenFiles::default(),
+            redirect_trailing_slash: true,
+            headers: vec![],
+            cache_rules: vec![],
+            compression: None,
+            autoindex: false,
+        }
+    }
+
+    /// File names tried in order when a directory is requested.
+    pub fn index<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.index = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// File sent with `200 OK` for missing paths, relative to the root, e.g.
+    /// `index.html` for a single page app. Paths whose last segment has an
+    /// extension still get a `404`, so a missing asset isn't answered with
+    /// a page.
+    pub fn fallback(mut self, file: impl Into<PathBuf>) -> Self {
+        self.fallback = Some(file.into());
+        self
+    }
+
+    /// File sent with `404 Not Found` for missing paths, relative to the root.
+    /// A plain text response is sent if it doesn't exist.
+    pub fn not_found_page(mut self, file: impl Into<PathBuf>) -> Self {
+        self.not_found = Some(file.into());
+        self
+    }
+
+    /// Sets what happens to hidden files, which are ignored by default.
+    pub fn hidden_files(mut self, hidden: HiddenFiles) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Whether a directory requested without a trailing slash is redirected
+    /// to the path with one, so relative links in its index resolve against
+    /// the directory. Enabled by default, otherwise the index is served as is.
+    pub fn redirect_trailing_slash(mut self, redirect: bool) -> Self {
+        self.redirect_trailing_slash = redirect;
+        self
+    }
+
+    /// Adds a header to every response, e.g. `Access-Control-Allow-Origin`.
+    pub fn header(mut self, key: &str, value: impl ToString) -> Self {
+        self.headers.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sends `Cache-Control: value` with files whose path below the root
+    /// matches `pattern`, where `*` matches anything including `/`, e.g.
+    /// `*.js`, `/assets/*` or `/index.html`. The first matching rule wins,
+    /// files matching none are sent without a `Cache-Control` header.
+    ///
+    /// ```
+    /// use rust_server::core::server::StaticFiles;
+    ///
+    /// let files = StaticFiles::new("./dist")
+    ///     .cache_control("/assets/*", "public, max-age=31536000, immutable")
+    ///     .cache_control("*.html", "no-cache")
+    ///     .cache_control("*", "public, max-age=3600");
+    ///
+    /// assert_eq!(files.cache_control_for("/index.html"), Some("no-cache"));
+    /// assert_eq!(files.cache_control_for("/logo.png"), Some("public, max-age=3600"));
+    /// ```
+    pub fn cache_control(mut self, pattern: &str, value: &str) -> Self {
+        self.cache_rules
+            .push((pattern.to_string(), value.to_string()));
+        self
+    }
+
+    /// The `Cache-Control` value for a path below the root, if a rule matches.
+    pub fn cache_control_for(&self, path: &str) -> Option<&str> {
+        self.cache_rules
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern, path))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Compresses files the client accepts compressed, see `Compression`.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Lists the contents of directories without an index file, as an HTML
+    /// page or as JSON for clients which `Accept: application/json`. The
+    /// listing is sorted with the `sort` (`name`, `size` or `modified`) and
+    /// `order` (`asc` or `desc`) query parameters, and leaves out hidden
+    /// files unless they're allowed. Off by default.
+    pub fn autoindex(mut self, autoindex: bool) -> Self {
+        self.autoindex = autoindex;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Serves `path`, the part of the request path below the mount point.
+    pub fn serve(&self, request: &mut Request, path: &str) -> RouteActions {
+        if is_hidden(path) {
+            match self.hidden {
+                HiddenFiles::Allow => {}
+                HiddenFiles::Deny => return request.send(self.head(403).text("Forbidden")),
+                HiddenFiles::Ignore => return self.missing(request, path),
+            }
+        }
+
+        let Some(file) = util::resolve_path(&self.root, path) else {
+            return self.missing(request, path);
+        };
+        if !file.is_dir() {
+            return self.send(request, &file, 200);
+        }
+
+        if !path.ends_with('/') && self.redirect_trailing_slash {
+            let location = match request.query() {
+                Some(query) => format!("{}?{}", directory_url(request.path()), query),
+                None => directory_url(request.path()),
+            };
+            return request.send(self.head(301).header("Location", location));
+        }
+
+        match self
+            .index
+            .iter()
+            .map(|name| file.join(name))
+            .find(|index| index.is_file())
+        {
+            Some(index) => self.send(request, &index, 200),
+            None if self.autoindex => self.list(request, &file, path),
+            None => self.missing(request, path),
+        }
+    }
+
+    fn list(&self, request: &mut Request, dir: &Path, path: &str) -> RouteActions {
+        let sort = Sort::from_query(request.query());
+        let entries = autoindex::read_entries(dir, self.hidden == HiddenFiles::Allow, sort)?;
+
+        let accept = request.headers().get("Accept").unwrap_or_default();
+        let json = accept.contains("application/json") && !accept.contains("text/html");
+        let head = self.head(200).header("Vary", "Accept");
+        let response = match json {
+            true => head.json(autoindex::render_json(&entries)),
+            false => {
+                let base = directory_url(request.path());
+                let parent = !path.trim_matches('/').is_empty();
+                head.html(autoindex::render_html(&base, &entries, sort, parent))
+            }
+        };
+        request.send(response)
+    }
+
+    fn missing(&self, request: &mut Request, path: &str) -> RouteActions {
+        let asset = path
+            .rsplit('/')
+            .next()
+            .is_some_and(|name| name.contains('.'));
+        let fallback = self.fallback.as_ref().filter(|_| !asset);
+        if let Some(fallback) = fallback.map(|file| self.root.join(file)) {
+            if fallback.is_file() {
+                return self.send(request, &fallback, 200);
+            }
+        }
+
+        match self.not_found.as_ref().map(|file| self.root.join(file)) {
+            Some(page) if page.is_file() => self.send(request, &page, 404),
+            _ => request.send(self.head(404).text("Not Found")),
+        }
+    }
+
+    fn send(&self, request: &mut Request, file: &Path, status: u16) -> RouteActions {
+        let mut head = self.head(status);
+        if status == 200 && !self.cache_rules.is_empty() {
+            if let Some(value) = self.cache_control_for(&self.relative(file)) {
+                head = head.header("Cache-Control", value);
+            }
+        }
+
+        let path = file.display().to_string();
+        let mime = get_mime_type(&path);
+        let Some(compression) = self.compression.as_ref().filter(|_| is_compressible(&mime)) else {
+            return util::send_file(request, &path, head);
+        };
+
+        // the body depends on `Accept-Encoding` whichever coding is picked
+        let head = head
+            .header("Content-Type", &mime)
+            .header("Vary", "Accept-Encoding");
+        let accept_encoding = request.headers().get("Accept-Encoding").unwrap_or_default();
+
+        if compression.precompressed {
+            let siblings: Vec<_> = [ContentEncoding::Brotli, ContentEncoding::Gzip]
+                .into_iter()
+                .filter_map(|encoding| {
+                    let sibling = format!("{}.{}", path, encoding.extension()?);
+                    Path::new(&sibling).is_file().then_some((encoding, sibling))
+                })
+                .collect();
+            let offered: Vec<_> = siblings.iter().map(|(encoding, _)| *encoding).collect();
+            let encoding = ContentEncoding::negotiate(accept_encoding, &offered);
+            if let Some((encoding, sibling)) = siblings.iter().find(|(e, _)| *e == encoding) {
+                let head = head.header("Content-Encoding", encoding.as_str());
+                return util::send_file(request, sibling, head);
+            }
+        }
+
+        let size = fs::metadata(file)?.len();
+        let encoding =
+            match compression.applies_to(&mime, size) && size <= compression.max_file_size {
+                true => ContentEncoding::negotiate(accept_encoding, &compression.encodings),
+                false => ContentEncoding::Identity,
+            };
+        util::send_file_encoded(request, &path, head, encoding)
+    }
+
+    /// The path of a file below the root, starting with `/`.
+    fn relative(&self, file: &Path) -> String {
+        let root = self
+            .root
+            .canonicalize()
+            .unwrap_or_else(|_| self.root.clone());
+        let relative = file
+            .strip_prefix(&root)
+            .or_else(|_| file.strip_prefix(&self.root))
+            .unwrap_or(file);
+        let segments: Vec<_> = relative
+            .iter()
+            .map(|segment| segment.to_string_lossy())
+            .collect();
+        format!("/{}", segments.join("/"))
+    }
+
+    fn head(&self, status: u16) -> HttpResponse {
+        self.headers
+            .iter()
+            .fold(HttpResponse::new(status), |head, (key, value)| {
+                head.header(key, value)
+            })
+    }
+}
+
+/// Matches text against a pattern where `*` matches any run of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let Some((prefix, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(text) = text.strip_prefix(prefix) else {
+        return false;
+    };
+    (0..=text.len())
+        .filter(|&i| text.is_char_boundary(i))
+        .any(|i| glob_match(rest, &text[i..]))
+}
+
+/// The request path of a directory ending with a single `/`, and starting
+/// with a single one too, as `//example.com/` would link to another host.
+fn directory_url(path: &str) -> String {
+    let path = path.trim_start_matches('/').trim_end_matches('/');
+    match path.is_empty() {
+        true => "/".to_string(),
+        false => format!("/{}/", path),
+    }
+}
+
+/// Whether any segment of the decoded path starts with a dot.
+fn is_hidden(path: &str) -> bool {
+    util::percent_decode(path)
+        .split('/')
+        .any(|segment| segment.starts_with('.') && segment != "." && segment != "..")
+}
@@ -1,81 +1,136 @@
+use crate::core::data::log::error;
+use std::collections::VecDeque;
 use std::io::Error;
-use std::net::TcpStream;
-use std::sync::{mpsc, Arc, Mutex};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 
-use crate::core::http::http_codec::HttpResponse;
-use crate::core::{ArcRwLock, Request, ThreadSafe};
-
-use super::server::Server;
-use super::{RouteHandler, Routes};
-
-pub type Operation = Box<dyn FnOnce() -> Result<u8, Error> + Sync + Send + 'static>;
+pub type Operation = Box<dyn FnOnce() -> Result<u16, Error> + Sync + Send + 'static>;
 
 pub enum Message {
-    Handle(Arc<Mutex<Vec<Operation>>>),
+    Handle(Operation),
     Shutdown,
 }
 
-pub type WorkerReceiver = Arc<Mutex<mpsc::Receiver<Message>>>;
+/// The queue shared by every worker in a pool. Idle workers wait on
+/// `available` and take the oldest message first.
+#[derive(Default)]
+struct Queue {
+    messages: Mutex<VecDeque<Message>>,
+    available: Condvar,
+}
+
+impl Queue {
+    fn push(&self, message: Message) {
+        self.messages.lock().unwrap().push_back(message);
+        self.available.notify_one();
+    }
+
+    /// Blocks until a message is available.
+    fn pop(&self) -> Message {
+        let mut messages = self.messages.lock().unwrap();
+        loop {
+            match messages.pop_front() {
+                Some(message) => return message,
+                None => messages = self.available.wait(messages).unwrap(),
+            }
+        }
+    }
+}
 
 pub struct Worker {
     id: usize,
-    sender: mpsc::Sender<Message>,
     thread: Option<thread::JoinHandle<()>>,
-    operations: Arc<Mutex<Vec<Operation>>>,
 }
 
 impl Worker {
-    pub fn new(id: usize) -> Worker {
-        let (sender, receiver) = mpsc::channel();
-
+    fn new(id: usize, queue: Arc<Queue>) -> Worker {
         Worker {
             id,
-            sender,
-            operations: Arc::new(Mutex::new(Vec::new())),
             thread: Some(thread::spawn(move || loop {
-                let message = match receiver.recv() {
-                    Ok(message) => message,
-                    Err(e) => {
-                        eprintln!("[worker] #{} error receiving message: {:?}", id, e);
-                        break;
-                    }
+                let operation = match queue.pop() {
+                    Message::Handle(operation) => operation,
+                    Message::Shutdown => break,
                 };
 
-                match message {
-                    Message::Shutdown => break,
-                    Message::Handle(operations) => match operations.try_lock() {
-                        Ok(mut operations) => {
-                            while let Some(operation) = operations.pop() {
-                                if let Err(e) = operation() {
-                                    eprintln!("[worker] #{} error handling operation: {:?}", id, e);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("[worker] #{} error locking operations: {:?}", id, e);
-                        }
-                    },
+                // a panicking handler only loses its own connection, not the worker
+                match panic::catch_unwind(AssertUnwindSafe(operation)) {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => {
+                        error!("[worker] #{} error handling operation: {:?}", id, e);
+                    }
+                    Err(_) => error!("[worker] #{} operation panicked", id),
                 }
             })),
         }
     }
 
-    pub fn enqueue(&mut self, operation: Operation) {
-        self.operations.lock().unwrap().push(operation);
-        match self.sender.send(Message::Handle(self.operations.clone())) {
-            Ok(_) => {}
-            Err(e) => {
-                eprintln!("[worker] #{} error sending message: {:?}", self.id, e);
-            }
-        }
-    }
-
     pub fn id(&self) -> usize {
         self.id
     }
 
-    pub fn join(self) -> thread::Result<()> {
-        self.thread.unwrap().join()
+    pub fn join(mut self) -> thread::Result<()> {
+        self.thread.take().unwrap().join()
+    }
+}
+
+/// Worker Pool
+///
+/// A fixed set of worker threads sharing a single FIFO queue, so whichever
+/// worker is free takes the oldest operation and nothing waits behind a slow
+/// request while another worker sits idle. The queue itself is unbounded,
+/// callers check `queued` to apply backpressure.
+pub struct WorkerPool {
+    workers: Vec<Worker>,
+    queue: Arc<Queue>,
+}
+
+impl WorkerPool {
+    pub fn new(threads: usize) -> Self {
+        let queue = Arc::new(Queue::default());
+        let workers = (0..threads.max(1))
+            .map(|id| Worker::new(id, queue.clone()))
+            .collect();
+
+        WorkerPool { workers, queue }
+    }
+
+    /// Queues an operation for the next free worker.
+    pub fn execute(&self, operation: Operation) {
+        self.queue.push(Message::Handle(operation));
+    }
+
+    /// The number of operations waiting for a worker.
+    pub fn queued(&self) -> usize {
+        self.queue.messages.lock().unwrap().len()
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Drops the operations which haven't started yet, returning how many.
+    pub fn clear(&self) -> usize {
+        let mut messages = self.queue.messages.lock().unwrap();
+        let cleared = messages.len();
+        messages.clear();
+        cleared
+    }
+
+    /// Stops every worker once the operations queued before this call are done.
+    pub fn shutdown(&self) {
+        for _ in &self.workers {
+            self.queue.push(Message::Shutdown);
+        }
+    }
+
+    /// Waits for every worker to stop, call `shutdown` first.
+    pub fn join(self) {
+        for worker in self.workers {
+            let id = worker.id();
+            if let Err(e) = worker.join() {
+                error!("[worker] #{} panicked: {:?}", id, e);
+            }
+        }
     }
 }
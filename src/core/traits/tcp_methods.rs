@@ -1,10 +1,10 @@
+use crate::core::data::log::info;
+use crate::core::http::{HttpCodec, HttpResponse};
 use std::{
     io::Write,
     net::{TcpListener, TcpStream},
 };
 
-static KEEP_ALIVE: &[u8] = b"HTTP/2.0 200 OK\r\nContent-Length: 0\r\n\r\n";
-
 pub trait TcpMethods {
     fn is_connected(&self) -> bool;
     fn is_keep_alive(&self) -> bool;
@@ -24,8 +24,10 @@ impl TcpMethods for TcpStream {
     }
 
     fn send_keep_alive(&mut self) -> std::io::Result<()> {
-        println!("[tcp_methods] sending keep_alive!");
-        self.write_all(KEEP_ALIVE)?;
+        info!("[tcp_methods] sending keep_alive!");
+        HttpResponse::ok()
+            .header("Connection", "keep-alive")
+            .encode_to(self)?;
         self.flush()?;
         Ok(())
     }
@@ -14,6 +14,12 @@ pub trait ArcRwLock<T>: Sync + Send + 'static {
 
 pub struct ThreadSafe<T>(Arc<RwLock<T>>);
 
+impl<T> Clone for ThreadSafe<T> {
+    fn clone(&self) -> Self {
+        ThreadSafe(self.0.clone())
+    }
+}
+
 impl<T> ThreadSafe<T> {
     pub fn new(value: T) -> Self {
         ThreadSafe(Arc::new(RwLock::new(value)))
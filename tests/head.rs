@@ -0,0 +1,90 @@
+use rust_server::core::http::HttpResponse;
+use rust_server::core::server::{Server, StaticFiles};
+use rust_server::core::Request;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::{fs, thread};
+
+fn hello(request: &mut Request) -> std::io::Result<u16> {
+    request.send(HttpResponse::ok().text("hello"))
+}
+
+fn start_server(root: PathBuf) -> SocketAddr {
+    let mut server = Server::new("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    server.configure(|route| {
+        route.mount("/files", StaticFiles::new(root));
+        route.def("GET", "/hello", hello);
+    });
+    thread::spawn(move || server.start());
+    addr
+}
+
+/// Sends the requests on one connection, the last of which must close it.
+fn exchange(addr: SocketAddr, requests: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(requests).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+/// Splits back to back responses into their heads, checking that whatever
+/// follows each head is exactly `Content-Length` bytes of body, or none for
+/// a response to `HEAD`.
+fn response_heads(mut response: &str, methods: &[&str]) -> Vec<String> {
+    let mut heads = vec![];
+    for method in methods {
+        let (head, rest) = response.split_once("\r\n\r\n").expect("missing head");
+        let length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .map_or(0, |length| length.parse().unwrap());
+        let length = if *method == "HEAD" { 0 } else { length };
+        heads.push(head.to_string());
+        response = &rest[length..];
+    }
+    assert!(response.is_empty(), "trailing bytes: {:?}", response);
+    heads
+}
+
+#[test]
+fn head_responses_have_no_body_on_a_kept_alive_connection() {
+    let root = std::env::temp_dir().join(format!("rust-server-head-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("file.txt"), "some text").unwrap();
+
+    let response = exchange(
+        start_server(root.clone()),
+        b"HEAD /files/nope HTTP/1.1\r\nHost: test\r\n\r\n\
+          HEAD /files/file.txt HTTP/1.1\r\nHost: test\r\n\r\n\
+          HEAD /hello HTTP/1.1\r\nHost: test\r\n\r\n\
+          GET /hello HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+    );
+    fs::remove_dir_all(&root).unwrap();
+
+    let heads = response_heads(&response, &["HEAD", "HEAD", "HEAD", "GET"]);
+    assert!(
+        heads[0].starts_with("HTTP/1.1 404 Not Found"),
+        "{}",
+        heads[0]
+    );
+    assert!(heads[0].contains("Content-Length: 9"), "{}", heads[0]);
+    assert!(heads[1].starts_with("HTTP/1.1 200 OK"), "{}", heads[1]);
+    assert!(heads[1].contains("Content-Length: 9"), "{}", heads[1]);
+    assert!(heads[2].starts_with("HTTP/1.1 200 OK"), "{}", heads[2]);
+    assert!(heads[2].contains("Content-Length: 5"), "{}", heads[2]);
+    assert!(heads[3].starts_with("HTTP/1.1 200 OK"), "{}", heads[3]);
+    assert!(response.ends_with("\r\n\r\nhello"), "{:?}", response);
+}
+
+#[test]
+fn allow_lists_head_with_get() {
+    let response = exchange(
+        start_server(std::env::temp_dir().join("rust-server-head-missing")),
+        b"POST /hello HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 405"), "{}", response);
+    assert!(response.contains("Allow: GET, HEAD\r\n"), "{}", response);
+}
@@ -0,0 +1,94 @@
+use rust_server::core::server::Server;
+use rust_server::core::Request;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+
+/// Echoes the method, uri and body length so each response identifies its request.
+fn echo(request: &mut Request) -> std::io::Result<u16> {
+    let body = format!(
+        "{} {} {}",
+        request.method(),
+        request.uri(),
+        request.body().map_or(0, |body| body.len())
+    );
+    write!(
+        request.stream(),
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: {}\r\n\r\n{}",
+        body.len(),
+        request.connection_header(),
+        body
+    )?;
+    Ok(200)
+}
+
+fn start_server() -> SocketAddr {
+    let mut server = Server::new("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    server.configure(|route| {
+        route.def("GET", "*", echo);
+        route.def("POST", "*", echo);
+    });
+    thread::spawn(move || server.start());
+    addr
+}
+
+/// Splits a buffer of back to back responses into their bodies using `Content-Length`.
+fn response_bodies(mut bytes: &[u8]) -> Vec<String> {
+    let mut bodies = vec![];
+    while let Some(end) = bytes.windows(4).position(|w| w == b"\r\n\r\n") {
+        let head = std::str::from_utf8(&bytes[..end]).unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"), "bad head: {}", head);
+        let length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = &bytes[end + 4..end + 4 + length];
+        bodies.push(String::from_utf8(body.to_vec()).unwrap());
+        bytes = &bytes[end + 4 + length..];
+    }
+    assert!(bytes.is_empty(), "trailing bytes: {:?}", bytes);
+    bodies
+}
+
+#[test]
+fn pipelined_gets_are_answered_in_order() {
+    let mut stream = TcpStream::connect(start_server()).unwrap();
+    stream
+        .write_all(
+            b"GET /one HTTP/1.1\r\nHost: test\r\n\r\n\
+              GET /two HTTP/1.1\r\nHost: test\r\n\r\n\
+              GET /three HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).unwrap();
+
+    assert_eq!(
+        response_bodies(&response),
+        vec!["GET /one 0", "GET /two 0", "GET /three 0"]
+    );
+}
+
+#[test]
+fn pipelined_request_bodies_are_not_mistaken_for_requests() {
+    let mut stream = TcpStream::connect(start_server()).unwrap();
+    stream
+        .write_all(
+            b"POST /upload HTTP/1.1\r\nContent-Length: 18\r\n\r\nGET /fake HTTP/1.1\
+              POST /chunked HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n\
+              GET /last HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).unwrap();
+
+    assert_eq!(
+        response_bodies(&response),
+        vec!["POST /upload 18", "POST /chunked 3", "GET /last 0"]
+    );
+}